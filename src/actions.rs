@@ -1,35 +1,35 @@
 use anyhow::{anyhow, bail, Result};
-use futures_util::stream::StreamExt;
-use once_cell::sync::Lazy;
-
-use regex::Regex;
 use reqwest::IntoUrl;
-use std::{ffi::OsString, fs, os::windows::process::CommandExt, path::Path, io::BufWriter};
+use std::{
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    fs,
+    path::{Path, PathBuf},
+};
 use tempfile::{NamedTempFile, TempDir, TempPath};
 use tokio::runtime::Runtime;
 
 use crate::{
+    addonlist::{Addons, Mo2Plugins},
     app::AppContext,
     backup::{BasicTransaction, SafeTransaction, Transaction},
+    mo2::{Mo2Instance, MO2_INI},
+    net::{
+        download_archive, download_file, find_link, get_text, DownloadProgress, GithubLink,
+        LinkResolver,
+    },
+    stats::{MO2_BACKUP, MODDED_EXES_BACKUP},
 };
 
-static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
-    reqwest::Client::builder()
-        .user_agent("Anomaly-Modder-Tool")
-        .build()
-        .unwrap()
-});
-
-static LINKS_REGEX: Lazy<Regex> = Lazy::new(|| regex::Regex::new("href=\"([^\"]*)\"").unwrap());
-
 static MODORG_INI: &str = include_str!("../resources/ModOrganizer.ini");
 static NXMHANDLER: &str = include_str!("../resources/nxmhandler.ini");
 
 /* static VANILLA_EXES: &[u8] = include_bytes!("../resources/Vanilla_Exes.zip"); */
 
 static URL_7ZIP: &str = "https://www.7-zip.org/a/7zr.exe";
-static URL_MODORG: &str = "https://github.com/ModOrganizer2/modorganizer/releases";
+static REPO_MODORG: &str = "ModOrganizer2/modorganizer";
 static URL_MODDED_EXES: &str = "https://github.com/themrdemonized/STALKER-Anomaly-modded-exes";
+pub static MODDED_EXES_RECORD: &str = "amt_modded_exes.txt";
 
 pub struct Unpacker7Zip<P: AsRef<Path>> {
     path: P,
@@ -37,6 +37,26 @@ pub struct Unpacker7Zip<P: AsRef<Path>> {
 
 pub trait Unpack7Zip: Copy {
     fn unpack(&self, file_path: &Path, out_dir: &Path) -> Result<()>;
+    fn list(&self, file_path: &Path) -> Result<Vec<ArchiveEntry>>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+// 7z and tasklist are console programs
+pub fn hidden_command(program: impl AsRef<OsStr>) -> std::process::Command {
+    #[allow(unused_mut)]
+    let mut command = std::process::Command::new(program);
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(0x08000000); // Create no console window
+    }
+    command
 }
 
 impl<P: AsRef<Path>> Unpacker7Zip<P> {
@@ -44,9 +64,8 @@ impl<P: AsRef<Path>> Unpacker7Zip<P> {
         let path_str = path.as_ref().as_os_str();
 
         if cfg!(debug_assertions) {
-            let successful = std::process::Command::new(path_str)
+            let successful = hidden_command(path_str)
                 .args(["i".to_owned()])
-                .creation_flags(0x08000000) // Create no console window
                 .status()
                 .expect("7zip path is not executable")
                 .success();
@@ -68,9 +87,8 @@ impl<P: AsRef<Path>> Unpack7Zip for &Unpacker7Zip<P> {
 
             x
         };
-        let status = std::process::Command::new(self.path.as_ref().as_os_str())
+        let status = hidden_command(self.path.as_ref().as_os_str())
             .args([&cmd, &out_arg, file_path.as_os_str()])
-            // .creation_flags(0x08000000) // Create no console window
             .status()?;
 
         if status.success() {
@@ -79,6 +97,44 @@ impl<P: AsRef<Path>> Unpack7Zip for &Unpacker7Zip<P> {
             bail!("7zip was not successful")
         }
     }
+
+    fn list(&self, file_path: &Path) -> Result<Vec<ArchiveEntry>> {
+        let output = hidden_command(self.path.as_ref().as_os_str())
+            .args(["l".as_ref(), "-slt".as_ref(), file_path.as_os_str()])
+            .output()?;
+
+        if !output.status.success() {
+            bail!("7zip can't list {}", file_path.display());
+        }
+        Ok(parse_7z_listing(&String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+// `7z l -slt` prints archive properties, a "----------" line,
+// then one "Key = Value" block per entry separated by empty lines
+fn parse_7z_listing(output: &str) -> Vec<ArchiveEntry> {
+    let Some((_, entries)) = output.split_once("\n----------") else {
+        return Vec::new();
+    };
+    entries
+        .replace("\r\n", "\n")
+        .split("\n\n")
+        .filter_map(|block| {
+            let field = |key: &str| {
+                block.lines().find_map(|l| {
+                    l.strip_prefix(key)
+                        .and_then(|v| v.strip_prefix(" = "))
+                        .map(str::trim)
+                })
+            };
+            Some(ArchiveEntry {
+                path: PathBuf::from(field("Path")?.replace('\\', "/")),
+                size: field("Size").and_then(|s| s.parse().ok()).unwrap_or(0),
+                is_dir: field("Folder") == Some("+")
+                    || field("Attributes").is_some_and(|a| a.starts_with('D')),
+            })
+        })
+        .collect()
 }
 
 pub trait AppAction {
@@ -92,46 +148,27 @@ pub trait AppAction {
     ) -> Result<Self::Output>;
 }
 
-#[derive(Default, Clone)]
-pub struct DownloadProgress {
-    pub file_name: Option<String>,
-    pub size: Option<u64>,
-    pub downloaded: u64,
-}
-
 pub struct InstallMo2;
 
 impl InstallMo2 {
-    async fn scrape_mo2_url() -> Result<impl IntoUrl> {
-        let resp = CLIENT.get(URL_MODORG).send().await?.text().await?;
-
-        let tag = LINKS_REGEX
-            .captures_iter(&resp)
-            .map(|s| s.get(1).unwrap())
-            .find(|s| s.as_str().contains("tag/") && !s.as_str().contains("rc"))
-            .map(|s| {
-                s.as_str()
-                    .split('/')
-                    .last()
-                    .unwrap()
-                    .to_owned()
-                    .replace('v', "")
-            })
-            .ok_or_else(|| anyhow!("Couldn't find last version tag"))?;
-        Ok(format!(
-            "https://github.com/ModOrganizer2/modorganizer/releases/download/v{0}/Mod.Organizer-{0}.7z",
-            tag
-        ))
-    }
-
     async fn download_mod_org(
         progress_callback: impl FnMut(&DownloadProgress),
     ) -> Result<tempfile::NamedTempFile> {
-        let url = Self::scrape_mo2_url().await?;
+        let release = GithubLink {
+            repo: REPO_MODORG.to_owned(),
+            tag: "latest".to_owned(),
+            filename: "Mod.Organizer-$VERSION.7z".to_owned(),
+        };
+        let url = release.download_url().await?;
         download_file(url, tempfile::NamedTempFile::new()?, progress_callback).await
     }
 
-    fn configure_mo2(mo_path: &Path, anomaly_path: &Path) -> Result<()> {
+    // A detected instance keeps its own ini, ours would point MO2 at the wrong folders
+    fn writes_ini(mo_dir: &Path, detected: Option<&Mo2Instance>) -> bool {
+        !mo_dir.join(MO2_INI).exists() && detected.is_none_or(|mo2| mo2.base_dir == mo_dir)
+    }
+
+    fn configure_mo2(mo_path: &Path, anomaly_path: &Path, write_ini: bool) -> Result<()> {
         let anomaly_path_str = anomaly_path.to_str().unwrap();
         let content: Vec<u8> = MODORG_INI
             .lines()
@@ -146,8 +183,10 @@ impl InstallMo2 {
             .flat_map(|s| s.bytes().collect::<Vec<_>>())
             .collect();
 
-        let mut modorg_config = std::fs::File::create(mo_path.join("ModOrganizer.ini"))?;
-        std::io::copy(&mut content.as_slice(), &mut modorg_config)?;
+        if write_ini {
+            let mut modorg_config = std::fs::File::create(mo_path.join(MO2_INI))?;
+            std::io::copy(&mut content.as_slice(), &mut modorg_config)?;
+        }
 
         let mut nxm = std::fs::File::create(mo_path.join("nxmhandler.ini"))?;
         std::io::copy(&mut NXMHANDLER.as_bytes(), &mut nxm)?;
@@ -160,16 +199,17 @@ pub struct InstallMo2Progress {
     pub download: Option<DownloadProgress>,
     pub unpacking_done: Option<bool>,
     pub configuring_done: Option<bool>,
+    pub plugins_done: Option<bool>,
     pub finished: bool,
 }
 
 impl AppAction for InstallMo2 {
     type Output = ();
     type Progress = InstallMo2Progress;
-    type Config = ();
+    type Config = Mo2Plugins;
 
     fn run(
-        _config: Self::Config,
+        plugins: Self::Config,
         ctx: impl AsRef<AppContext>,
         mut progress_callback: impl FnMut(&Self::Progress),
     ) -> Result<Self::Output> {
@@ -194,15 +234,26 @@ impl AppAction for InstallMo2 {
         progress.configuring_done = Some(false);
         progress_callback(&progress);
 
-        Self::configure_mo2(modorg_tmp.path(), &ctx.anomaly_dir)?;
+        // MO2 itself and its plugins always go next to the game, the instance may live elsewhere
+        let mo_dir = ctx.anomaly_dir.join("mo2");
+        let write_ini = Self::writes_ini(&mo_dir, ctx.mo2.as_ref());
+        Self::configure_mo2(modorg_tmp.path(), &ctx.anomaly_dir, write_ini)?;
 
         progress.configuring_done = Some(true);
 
         let tr = BasicTransaction::new(modorg_tmp)?;
 
-        let mo_dir = ctx.anomaly_dir.join("mo2");
-        let backup_dir = ctx.anomaly_dir.join("BACKUP");
-        let done = SafeTransaction::new(&tr, &backup_dir)?.run(&mo_dir);
+        let backup_dir = ctx.anomaly_dir.join(MO2_BACKUP);
+        let done = SafeTransaction::new(&tr, &backup_dir)?.run(&mo_dir).and_then(|_| {
+            if plugins.is_empty() {
+                return Ok(());
+            }
+            progress.plugins_done = Some(false);
+            progress_callback(&progress);
+            Runtime::new()?.block_on(plugins.install(&mo_dir, unpacker_7zip))?;
+            progress.plugins_done = Some(true);
+            Ok(())
+        });
 
         progress.finished = true;
         progress_callback(&progress);
@@ -214,18 +265,17 @@ impl AppAction for InstallMo2 {
 pub struct InstallModdedExes;
 
 impl InstallModdedExes {
-    async fn download_modded_exes() -> Result<tempfile::NamedTempFile> {
-        let resp = CLIENT.get(URL_MODDED_EXES).send().await?.text().await?;
-        let url = format!(
+    pub async fn modded_exes_url() -> Result<String> {
+        let resp = get_text(URL_MODDED_EXES).await?;
+        Ok(format!(
             "https://github.com{}",
-            LINKS_REGEX
-                .captures_iter(&resp)
-                .map(|c| c.get(1).unwrap())
-                .find(|s| s.as_str().ends_with(".zip") && !s.as_str().ends_with("main.zip"))
-                .map(|s| s.as_str().replace("blob", "raw"))
+            find_link(&resp, |s| s.ends_with(".zip") && !s.ends_with("main.zip"))
+                .map(|s| s.replace("blob", "raw"))
                 .ok_or_else(|| anyhow!("Couldn't find the link for modded exes"))?
-        );
+        ))
+    }
 
+    async fn download_modded_exes(url: &str) -> Result<tempfile::NamedTempFile> {
         download_file(url, tempfile::NamedTempFile::new()?, |_p| {
             {};
         })
@@ -242,65 +292,24 @@ impl AppAction for InstallModdedExes {
         ctx: impl AsRef<AppContext>,
         _progress: impl FnMut(&Self::Progress),
     ) -> Result<Self::Output> {
-        let file = Runtime::new()?.block_on(Self::download_modded_exes())?;
+        let runtime = Runtime::new()?;
+        let url = runtime.block_on(Self::modded_exes_url())?;
+        let file = runtime.block_on(Self::download_modded_exes(&url))?;
         let tmp_dir = tempfile::tempdir()?;
         unpack_zip(file.as_file(), tmp_dir.path(), |_| {})?;
         let tr = BasicTransaction::new(tmp_dir)?;
-        let backup_dir = ctx.as_ref().anomaly_dir.join("BACKUP_Vanilla_exes");
-        SafeTransaction::new(&tr, &backup_dir)?.run(&ctx.as_ref().anomaly_dir)?;
+        let anomaly_dir = &ctx.as_ref().anomaly_dir;
+        let backup_dir = anomaly_dir.join(MODDED_EXES_BACKUP);
+        SafeTransaction::new(&tr, &backup_dir)?.run(anomaly_dir)?;
+        // the archive name changes with every release, update checks compare against it
+        fs::write(anomaly_dir.join(MODDED_EXES_RECORD), url)?;
         Ok(())
     }
 }
 
 pub async fn download_and_unpack(url: impl IntoUrl, unpacker: impl Unpack7Zip) -> Result<TempDir> {
-    let tmpfile = tempfile::NamedTempFile::new()?;
-    let buf = BufWriter::new(tmpfile);
-    let file = download_file(url, buf, |p| {
-        println!(
-            "Downloading {}: {}/{}",
-            p.file_name.as_ref().map(|s| s.to_owned()).unwrap_or_default(), p.downloaded, p.size.unwrap_or(0)
-        );
-    })
-    .await?;
-    unpack_temporary(unpacker, file.into_inner().unwrap(), |_| {})
-}
-
-pub async fn download_file<W: std::io::Write>(
-    url: impl IntoUrl,
-    mut file: W,
-    mut progress_callback: impl FnMut(&DownloadProgress),
-) -> Result<W> {
-    let regex = Regex::new("filename ?= ?\"?([[:^space:]]*)\"?").unwrap();
-    let response = CLIENT.get(url).send().await?;
-    let filename = response
-        .headers()
-        .get(http::header::CONTENT_DISPOSITION)
-        .iter()
-        .flat_map(|h| h.to_str())
-        .flat_map(|s| regex.captures(s))
-        .map(|c| c.get(1).unwrap().as_str().to_owned())
-        .next();
-    let mut progress = DownloadProgress {
-        file_name: filename,
-        size: response.content_length(),
-        downloaded: 0,
-    };
-    progress_callback(&progress);
-
-    let mut stream = response.bytes_stream();
-    let mut last_progress = 0;
-    while let Some(item) = stream.next().await {
-        let chunk = item?;
-        file.write_all(&chunk)?;
-
-        progress.downloaded += chunk.len() as u64;
-        if progress.downloaded - last_progress > 1024 * 100 {     // a bit less pressure
-            progress_callback(&progress);
-            last_progress = progress.downloaded;
-        }
-    }
-
-    Ok(file)
+    let archive = download_archive(url).await?;
+    unpack_temporary(unpacker, archive.file, |_| {})
 }
 
 pub async fn download_7zip() -> Result<Unpacker7Zip<TempPath>> {
@@ -320,15 +329,74 @@ pub fn unpack_temporary(
     file: NamedTempFile,
     progress_callback: impl FnMut(&UnpackZipProgress),
 ) -> Result<TempDir> {
-    let tempdir = tempfile::Builder::new().tempdir()?;
     let (file, path) = file.into_parts();
+    unpack_file(unpacker_7zip, file, &path, progress_callback)
+}
+
+pub fn unpack_path(unpacker_7zip: impl Unpack7Zip, path: &Path) -> Result<TempDir> {
+    unpack_file(unpacker_7zip, fs::File::open(path)?, path, |_| {})
+}
+
+fn unpack_file(
+    unpacker_7zip: impl Unpack7Zip,
+    file: fs::File,
+    path: &Path,
+    progress_callback: impl FnMut(&UnpackZipProgress),
+) -> Result<TempDir> {
+    let tempdir = tempfile::Builder::new().tempdir()?;
     let unpacked_zip = unpack_zip(&file, tempdir.path(), progress_callback);
     if unpacked_zip.is_ok() {
         return Ok(tempdir);
     }
 
     drop(file);
-    unpacker_7zip.unpack(&path, tempdir.path()).map(|_| tempdir)
+    unpacker_7zip.unpack(path, tempdir.path()).map(|_| tempdir)
+}
+
+// Reads only the archive index, nothing is extracted
+pub fn list_archive(unpacker_7zip: impl Unpack7Zip, path: &Path) -> Result<Vec<ArchiveEntry>> {
+    list_zip(path).or_else(|_| unpacker_7zip.list(path))
+}
+
+pub fn list_zip(path: &Path) -> Result<Vec<ArchiveEntry>> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+    (0..archive.len())
+        .map(|i| {
+            let file = archive.by_index(i)?;
+            Ok(ArchiveEntry {
+                path: file
+                    .enclosed_name()
+                    .ok_or_else(|| anyhow!("Zip is ill-formed!"))?
+                    .to_owned(),
+                size: file.size(),
+                is_dir: file.is_dir(),
+            })
+        })
+        .collect()
+}
+
+// Folders holding a gamedata folder, found the same way an install finds them
+pub fn addon_roots(entries: &[ArchiveEntry]) -> Vec<PathBuf> {
+    // archives don't always list folders on their own
+    let folders: HashSet<&Path> = entries
+        .iter()
+        .flat_map(|e| e.path.ancestors().skip(if e.is_dir { 0 } else { 1 }))
+        .filter(|p| p.file_name().is_some())
+        .collect();
+    let mut roots: Vec<PathBuf> = Addons::find_addons(folders.into_iter())
+        .into_iter()
+        .collect();
+    roots.sort();
+    roots
+}
+
+pub fn copy_temporary(dir: &Path) -> Result<TempDir> {
+    let tempdir = tempfile::Builder::new().tempdir()?;
+    let mut opt = fs_extra::dir::CopyOptions::new();
+    opt.content_only = true;
+    opt.copy_inside = true;
+    fs_extra::dir::copy(dir, tempdir.path(), &opt)?;
+    Ok(tempdir)
 }
 
 fn unpack_zip<R>(
@@ -373,3 +441,106 @@ where
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, path::PathBuf};
+
+    use tempfile::NamedTempFile;
+
+    use crate::mo2::Mo2Instance;
+
+    use super::{addon_roots, list_zip, parse_7z_listing, ArchiveEntry, InstallMo2};
+
+    static LISTING: &str = "
+7-Zip (r) 23.01 (x86) : Copyright (c) 1999-2023 Igor Pavlov : 2023-06-20
+
+Scanning the drive for archives:
+1 file, 2048 bytes (2 KiB)
+
+Listing archive: Igigui.7z
+
+--
+Path = Igigui.7z
+Type = 7z
+Physical Size = 2048
+
+----------
+Path = Igigui\\gamedata
+Size = 0
+Packed Size = 0
+Attributes = D....
+
+Path = Igigui\\gamedata\\scripts\\igigui.script
+Size = 4242
+Packed Size = 1500
+Attributes = A....
+";
+
+    #[test]
+    fn listing_7z() {
+        let entries = parse_7z_listing(&LISTING.replace('\n', "\r\n"));
+        assert_eq!(
+            entries,
+            [
+                ArchiveEntry {
+                    path: PathBuf::from("Igigui/gamedata"),
+                    size: 0,
+                    is_dir: true,
+                },
+                ArchiveEntry {
+                    path: PathBuf::from("Igigui/gamedata/scripts/igigui.script"),
+                    size: 4242,
+                    is_dir: false,
+                },
+            ]
+        );
+        assert_eq!(addon_roots(&entries), [PathBuf::from("Igigui")]);
+        assert!(parse_7z_listing("7-Zip: can't open archive").is_empty());
+    }
+
+    #[test]
+    fn listing_zip() {
+        let mut file = NamedTempFile::new().unwrap();
+        {
+            let mut zip = zip::ZipWriter::new(file.as_file_mut());
+            let options = zip::write::FileOptions::default();
+            zip.add_directory("gamedata/", options).unwrap();
+            zip.start_file("gamedata/configs/a.ltx", options).unwrap();
+            zip.write_all(b"[section]").unwrap();
+            zip.start_file("Patch/gamedata/b.ltx", options).unwrap();
+            zip.start_file("Nested/gamedata/gamedata/c.ltx", options).unwrap();
+            zip.start_file("Upper/Gamedata/d.ltx", options).unwrap();
+            zip.finish().unwrap();
+        }
+
+        let entries = list_zip(file.path()).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert!(entries[0].is_dir);
+        assert_eq!(entries[1].size, 9);
+        // same rules as an install: case-sensitive, every gamedata counts
+        assert_eq!(
+            addon_roots(&entries),
+            ["", "Nested", "Nested/gamedata", "Patch"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn mo2_ini_kept() {
+        let anomaly = tempfile::tempdir().unwrap();
+        let mo_dir = anomaly.path().join("mo2");
+        let global = anomaly.path().join("LocalAppData/ModOrganizer/Anomaly");
+
+        // fresh install, or an empty portable folder
+        assert!(InstallMo2::writes_ini(&mo_dir, None));
+        let portable = Mo2Instance::open(&mo_dir).unwrap();
+        assert!(InstallMo2::writes_ini(&mo_dir, Some(&portable)));
+
+        // a global instance, or a portable one that already has its ini
+        let global = Mo2Instance::open(&global).unwrap();
+        assert!(!InstallMo2::writes_ini(&mo_dir, Some(&global)));
+        std::fs::create_dir_all(&mo_dir).unwrap();
+        std::fs::write(mo_dir.join("ModOrganizer.ini"), "[Settings]\n").unwrap();
+        assert!(!InstallMo2::writes_ini(&mo_dir, None));
+    }
+}
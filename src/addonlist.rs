@@ -1,24 +1,31 @@
 use std::{
-    borrow::Cow,
-    collections::{hash_map::Entry, HashMap, HashSet},
+    collections::{HashMap, HashSet},
     io::Cursor,
     path::{Path, PathBuf},
 };
 
 use anyhow::{anyhow, bail, Result};
-use once_cell::sync::Lazy;
-use regex::Regex;
-use reqwest::IntoUrl;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use tempfile::{tempdir, TempDir};
 
 use crate::{
-    actions::{download_and_unpack, Unpack7Zip},
+    actions::{copy_temporary, download_and_unpack, unpack_path, Unpack7Zip},
     backup::{BasicTransaction, ComplexTransaction, InDir, SafeTransaction, Transaction},
-    config::ModpackConfig,
+    config::{InstanceConfigData, ModpackConfig, UserOverrides, BUNDLED_CONFIG},
+    hooks::Hook,
+    journal::{AddonState, InstallJournal},
+    mo2::{place_download, write_download_meta, Mo2Instance},
+    net::{
+        download_archive, print_progress, Archive, GithubLink, LinkResolver, ModdbLink,
+        TorrentLink, UrlLink,
+    },
+    processes,
+    stats::dir_size,
 };
 
+static PLUGINS_RECORD: &str = "amt_plugins.json";
 static LOADORDER_HEADER: &str =
     "# This file was automatically generated by Anomaly Modding Tool. Sorry if it broke lol.\n";
 
@@ -28,35 +35,146 @@ pub struct Modpack {
     order: LoadOrder,
 }
 
+#[derive(Default)]
+pub struct InstallOptions {
+    pub keep_archives: bool,
+    pub no_seeding: bool,
+}
+
 impl Modpack {
-    pub async fn install(&self, mo_dir: &Path, unpacker: impl Unpack7Zip) -> Result<()> {
-        let mut cache = DownloadCache::new();
-        let mut tr = ComplexTransaction::new();
-        for addon in self.addons.missing_addons(mo_dir) {
+    pub async fn install(
+        &self,
+        mo2: &Mo2Instance,
+        unpacker: impl Unpack7Zip,
+        options: &InstallOptions,
+    ) -> Result<()> {
+        let mods_dir = &mo2.mods_dir;
+        let mut journal = InstallJournal::load(&mo2.base_dir)?;
+        let mut instance = InstanceConfigData::load(&mo2.base_dir)?;
+
+        let mut pending: Vec<String> = self
+            .addons
+            .missing_addons(mods_dir)
+            .into_iter()
+            .map(|a| a.to_owned())
+            .collect();
+        for addon in journal.interrupted() {
+            // the folder is there, but we were killed while copying into it
+            if self.addons.get(addon).is_some() && !pending.iter().any(|a| a == addon) {
+                pending.push(addon.to_owned());
+            }
+        }
+
+        // several addons can come from one download, its archive goes to MO2 once
+        let mut placed: Vec<&AddonKey> = Vec::new();
+        for addon in &pending {
             let entry = self.addons.get(addon).unwrap();
-            let dl_dir = cache.get_or_download(&entry.download, unpacker).await?;
-            let addon_files = Addons::install(entry, &dl_dir)?;
-            tr.add(InDir::new(addon_files, addon));
+            journal.set_state(addon, AddonState::Installing)?;
+
+            let addon_dir = mods_dir.join(addon);
+            if addon_dir.exists() {
+                std::fs::remove_dir_all(&addon_dir)?;
+            }
+
+            let (staged, archive_size) = match journal.staged(&entry.download) {
+                Some(staged) => {
+                    let size = staged.archive.as_ref().and_then(|a| a.metadata().ok());
+                    (staged, size.map(|m| m.len()))
+                }
+                None => {
+                    let (unpacked, archive, size) =
+                        Self::download(&entry.download, mo2, &mut instance, unpacker, options).await?;
+                    // only staged when it's kept, it's a second copy of the download
+                    let archive = archive
+                        .as_ref()
+                        .filter(|_| options.keep_archives)
+                        .map(|a| (a.file.path(), a.file_name.as_str()));
+                    let staged = journal.stage(&entry.download, unpacked, archive)?;
+                    (staged, Some(size))
+                }
+            };
+            let archive = staged.archive.as_ref().filter(|_| options.keep_archives);
+            if let Some(archive) = archive.filter(|_| !placed.contains(&&entry.download)) {
+                let file_name = archive.file_name().unwrap().to_string_lossy();
+                let name =
+                    place_download(&mo2.downloads_dir, archive, &file_name, &entry.download)?;
+                instance.record_download(&name);
+                placed.push(&entry.download);
+            }
+            let dl_dir = staged.dir;
+
+            let hooked = entry.prepare(&dl_dir)?;
+            let unpacked = hooked.as_ref().map_or(dl_dir.as_path(), |d| d.path());
+            let tr = InDir::new(Addons::install(entry, unpacked)?, addon);
+            SafeTransaction::new(&tr, tempdir()?)?.run(mods_dir)?;
+            journal.set_state(addon, AddonState::Installed)?;
+            instance.record_install(addon, entry, dir_size(&addon_dir), archive_size);
+            instance.save()?;
         }
 
-        SafeTransaction::new(&tr, tempdir()?)?.run(&mo_dir.join("mods"))?;
-        Ok(())
+        journal.finish()
+    }
+
+    async fn download(
+        key: &AddonKey,
+        mo2: &Mo2Instance,
+        instance: &mut InstanceConfigData,
+        unpacker: impl Unpack7Zip,
+        options: &InstallOptions,
+    ) -> Result<(TempDir, Option<Archive>, u64)> {
+        if let AddonKey::Torrent(link) = key {
+            // torrents live in MO2 downloads like in any torrent client, so they can be seeded
+            let content = link
+                .download(&mo2.downloads_dir, !options.no_seeding, print_progress)
+                .await?;
+            let file_name = content.file_name().unwrap().to_string_lossy();
+            instance.record_download(&file_name);
+            instance.save()?;
+            let size = dir_size(&content);
+            if content.is_dir() {
+                return Ok((copy_temporary(&content)?, None, size));
+            }
+            write_download_meta(&mo2.downloads_dir, &file_name, key)?;
+            return Ok((unpack_path(unpacker, &content)?, None, size));
+        }
+
+        let url = key.download_link().await?;
+        let archive = download_archive(url).await?;
+        let size = archive.file.as_file().metadata()?.len();
+        let unpacked = unpack_path(unpacker, archive.file.path())?;
+        Ok((unpacked, Some(archive), size))
     }
 
-    pub fn enable(&self, mo_dir: &Path) -> Result<()> {
+    pub fn enable(&self, mo2: &Mo2Instance) -> Result<()> {
         let tmpdir = tempdir()?;
-        let profile = tmpdir.path().join("profiles/Default");
+        let profile = tmpdir.path().join("Default");
         std::fs::create_dir_all(&profile)?;
         let mut tmp = std::fs::File::create(profile.join("modlist.txt"))?;
         let mut out = Cursor::new(self.order.to_modorg_modlist());
         std::io::copy(&mut out, &mut tmp)?;
 
         let tr = BasicTransaction::new(tmpdir)?;
-        SafeTransaction::new(&tr, tempdir()?)?.run(mo_dir)?;
+        SafeTransaction::new(&tr, tempdir()?)?.run(&mo2.profiles_dir)?;
 
         Ok(())
     }
 
+    pub fn missing_addons(&self, mods_dir: &Path) -> Vec<&str> {
+        self.addons.missing_addons(mods_dir)
+    }
+
+    pub fn unknown_addons(&self, mods_dir: &Path) -> Result<Vec<String>> {
+        self.addons.unknown_addons(mods_dir)
+    }
+
+    pub fn load_order(&self) -> &[String] {
+        self.order.as_ref()
+    }
+
+    pub fn order(&self) -> &LoadOrder {
+        &self.order
+    }
+
     pub fn addons(&self) -> impl Iterator<Item = (&str, &FolderEntry)> {
         self.order
             .0
@@ -65,50 +183,129 @@ impl Modpack {
     }
 }
 
-impl From<ModpackConfig> for Modpack {
-    fn from(value: ModpackConfig) -> Self {
+impl TryFrom<ModpackConfig> for Modpack {
+    type Error = anyhow::Error;
+
+    fn try_from(value: ModpackConfig) -> Result<Self> {
         let mut pack = Modpack::default();
         for (folder, entry) in value.mods {
             pack.order.push(folder.clone());
             pack.addons.insert(folder, entry);
         }
-        pack
+        pack.order.resolve(&value.order)?;
+        Ok(pack)
     }
 }
 
-#[derive(Default)]
-pub struct DownloadCache<'a>(HashMap<&'a AddonKey, TempDir>);
+// Plugins are dropped straight into mo2/plugins, there's no mod folder per plugin,
+// so what's installed (and which version) is kept in a record next to MO2
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Mo2Plugins(IndexMap<String, FolderEntry>);
 
-impl<'a> DownloadCache<'a> {
-    async fn get_or_download(
-        &mut self,
-        key: &'a AddonKey,
-        unpacker: impl Unpack7Zip,
-    ) -> Result<PathBuf> {
-        dbg!(serde_json::to_string(key).unwrap());
-        let entry = self.0.entry(key);
-        let dir = match &entry {
-            Entry::Occupied(_) => None,
-            Entry::Vacant(_) => {
-                let url = key.download_link().await?;
-                let dl_dir = download_and_unpack(url, unpacker).await?;
-                Some(dl_dir)
-            }
+impl Mo2Plugins {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub async fn install(&self, mo_dir: &Path, unpacker: impl Unpack7Zip) -> Result<()> {
+        let record_path = mo_dir.join(PLUGINS_RECORD);
+        let mut installed: Addons = match std::fs::read_to_string(&record_path) {
+            Ok(s) => serde_json::from_str(&s)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Addons::default(),
+            Err(e) => return Err(e.into()),
         };
-        dbg!(&dir);
-        Ok(entry.or_insert_with(|| dir.unwrap()).path().to_owned())
+
+        for (name, entry) in &self.0 {
+            if installed.get(name) == Some(entry) {
+                continue;
+            }
+
+            let url = entry.download.download_link().await?;
+            let dl_dir = download_and_unpack(url, unpacker).await?;
+            Hook::apply_all(&entry.hooks, dl_dir.path())?;
+            let tr = Self::plugin_files(entry, dl_dir.path())?;
+            SafeTransaction::new(&tr, tempdir()?)?.run(&mo_dir.join("plugins"))?;
+
+            installed.insert(name.clone(), entry.clone());
+            std::fs::write(&record_path, serde_json::to_string_pretty(&installed)?)?;
+        }
+        Ok(())
     }
 
-    fn new() -> Self {
-        Self::default()
+    fn plugin_files(entry: &FolderEntry, dl_dir: &Path) -> Result<ComplexTransaction> {
+        let mut tr = ComplexTransaction::new();
+
+        // archive made to be extracted over MO2 root
+        let plugins = dl_dir.join("plugins");
+        if plugins.is_dir() {
+            tr.add(BasicTransaction::new(plugins)?);
+            return Ok(tr);
+        }
+
+        match &entry.addon_folder {
+            Some(folder) => {
+                let dir = walkdir::WalkDir::new(dl_dir)
+                    .into_iter()
+                    .filter_map(|d| d.ok())
+                    .find(|d| d.file_type().is_dir() && d.file_name() == folder.as_str())
+                    .ok_or_else(|| anyhow!("Can't find plugin folder {}", folder))?;
+                tr.add(InDir::new(BasicTransaction::new(dir.into_path())?, folder));
+            }
+            None => {
+                tr.add(BasicTransaction::new(PathBuf::from(dl_dir))?);
+            }
+        }
+        Ok(tr)
     }
 }
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct Addons(HashMap<String, FolderEntry>);
 
-#[derive(Default)]
-struct LoadOrder(Vec<String>);
+#[derive(Debug, Default, PartialEq)]
+pub struct LoadOrder(Vec<String>);
+
+// Constraints on top of the mod list order. Later addons win conflicts,
+// so "X after Y" means X overwrites Y.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OrderRules {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<AfterRule>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<PriorityGroup>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AfterRule {
+    pub addon: String,
+    pub after: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriorityGroup {
+    pub name: String,
+    pub priority: Priority,
+    pub addons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    First,
+    Last,
+}
+
+impl OrderRules {
+    pub fn is_empty(&self) -> bool {
+        self.after.is_empty() && self.groups.is_empty()
+    }
+
+    pub fn extend(&mut self, other: OrderRules) {
+        self.after.extend(other.after);
+        self.groups.extend(other.groups);
+    }
+}
 
 impl AsRef<[String]> for LoadOrder {
     fn as_ref(&self) -> &[String] {
@@ -137,7 +334,78 @@ impl LoadOrder {
         Ok(())
     }
 
-    fn to_modorg_modlist(&self) -> String {
+    // Stable topological sort: without rules, or when rules allow it, the list order is kept
+    fn resolve(&mut self, rules: &OrderRules) -> Result<()> {
+        let index = |addon: &str| self.0.iter().position(|a| a == addon);
+        let mut edges: Vec<(usize, usize)> = Vec::new();
+
+        for rule in &rules.after {
+            match (index(&rule.after), index(&rule.addon)) {
+                (Some(before), Some(after)) => edges.push((before, after)),
+                _ => eprintln!(
+                    "Warning: order rule {} after {} skipped, addon is not in the pack",
+                    rule.addon, rule.after
+                ),
+            }
+        }
+
+        let mut priority: Vec<Option<(Priority, &str)>> = vec![None; self.0.len()];
+        for group in &rules.groups {
+            for addon in &group.addons {
+                let Some(ix) = index(addon) else {
+                    eprintln!(
+                        "Warning: {} from group {} is not in the pack",
+                        addon, group.name
+                    );
+                    continue;
+                };
+                match priority[ix] {
+                    Some((p, other)) if p != group.priority => {
+                        bail!("{} is in both {} and {} groups", addon, other, group.name)
+                    }
+                    _ => priority[ix] = Some((group.priority, &group.name)),
+                }
+            }
+        }
+        let rank = |ix: usize| match priority[ix] {
+            Some((Priority::First, _)) => 0,
+            None => 1,
+            Some((Priority::Last, _)) => 2,
+        };
+        for a in 0..self.0.len() {
+            for b in 0..self.0.len() {
+                if rank(a) < rank(b) {
+                    edges.push((a, b));
+                }
+            }
+        }
+
+        let mut incoming = vec![0; self.0.len()];
+        for (_, to) in &edges {
+            incoming[*to] += 1;
+        }
+        let mut done = vec![false; self.0.len()];
+        let mut order = Vec::with_capacity(self.0.len());
+        while order.len() < self.0.len() {
+            let Some(next) = (0..self.0.len()).find(|i| !done[*i] && incoming[*i] == 0) else {
+                let stuck: Vec<&str> = (0..self.0.len())
+                    .filter(|i| !done[*i])
+                    .map(|i| self.0[i].as_str())
+                    .collect();
+                bail!("Load order rules contradict each other: {}", stuck.join(", "));
+            };
+            done[next] = true;
+            for (_, to) in edges.iter().filter(|(from, _)| *from == next) {
+                incoming[*to] -= 1;
+            }
+            order.push(next);
+        }
+
+        self.0 = order.into_iter().map(|i| self.0[i].clone()).collect();
+        Ok(())
+    }
+
+    pub fn to_modorg_modlist(&self) -> String {
         // ModOrg interprets the list in reversed order
         let mut list = LOADORDER_HEADER.to_owned();
         for addon in self.0.iter().rev() {
@@ -147,6 +415,73 @@ impl LoadOrder {
         }
         list
     }
+
+    // Same order MO2 shows in its left pane, lowest priority first
+    pub fn to_plain_text(&self) -> String {
+        self.0.iter().map(|a| format!("{}\n", a)).collect()
+    }
+
+    // Enabled addons of a modlist.txt, disabled ones, separators and DLCs are dropped
+    pub fn from_modorg_modlist(modlist: &str) -> Self {
+        let mut order: Vec<String> = modlist
+            .lines()
+            .filter_map(|l| l.trim().strip_prefix('+'))
+            .filter(|a| !a.is_empty() && !a.ends_with("_separator"))
+            .map(str::to_owned)
+            .collect();
+        order.reverse();
+        Self(order)
+    }
+
+    pub fn from_plain_text(text: &str) -> Self {
+        Self(
+            text.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(str::to_owned)
+                .collect(),
+        )
+    }
+
+    // Whatever users paste: a copy of modlist.txt or one addon per line
+    pub fn parse(text: &str) -> Self {
+        let is_modlist = text
+            .lines()
+            .map(str::trim_start)
+            .any(|l| l.starts_with('+') || l.starts_with('-') || l.starts_with('*'));
+        if is_modlist {
+            Self::from_modorg_modlist(text)
+        } else {
+            Self::from_plain_text(text)
+        }
+    }
+
+    pub fn into_inner(self) -> Vec<String> {
+        self.0
+    }
+
+    // Keeps the order in user overrides and writes it to MO2 when there is one.
+    // MO2 overwrites modlist.txt when it exits, `prompt` is asked until it's closed,
+    // nothing is saved if it gives up
+    pub fn import(
+        text: &str,
+        mo2: Option<&Mo2Instance>,
+        overrides_path: &Path,
+        prompt: impl FnMut(&[String]) -> bool,
+    ) -> Result<()> {
+        if mo2.is_some() {
+            processes::wait_until_closed(prompt)?;
+        }
+        let mut overrides = UserOverrides::load(overrides_path)?;
+        overrides.load_order = Self::parse(text).into_inner();
+        overrides.save(overrides_path)?;
+
+        let pack = Modpack::try_from(ModpackConfig::load(BUNDLED_CONFIG, overrides_path)?)?;
+        if let Some(mo2) = mo2 {
+            pack.enable(mo2)?;
+        }
+        Ok(())
+    }
 }
 
 impl Addons {
@@ -158,8 +493,7 @@ impl Addons {
         self.0.insert(key, val);
     }
 
-    fn missing_addons(&self, mo_dir: &Path) -> Vec<&str> {
-        let mods_dir = mo_dir.join("mods");
+    fn missing_addons(&self, mods_dir: &Path) -> Vec<&str> {
         self.0
             .keys()
             .filter(|m| !mods_dir.join(m).is_dir())
@@ -167,11 +501,22 @@ impl Addons {
             .collect()
     }
 
+    pub fn unknown_addons(&self, mods_dir: &Path) -> Result<Vec<String>> {
+        let mut unknown = Vec::new();
+        for dir in std::fs::read_dir(mods_dir)? {
+            let name = dir?.file_name().to_string_lossy().into_owned();
+            if self.get(&name).is_none() {
+                unknown.push(name);
+            }
+        }
+        Ok(unknown)
+    }
+
     pub fn get(&self, folder: &str) -> Option<&FolderEntry> {
         self.0.get(folder)
     }
 
-    fn find_addons(folders: impl Iterator<Item = impl AsRef<Path>>) -> HashSet<PathBuf> {
+    pub fn find_addons(folders: impl Iterator<Item = impl AsRef<Path>>) -> HashSet<PathBuf> {
         folders
             .filter(|d| d.as_ref().file_name().unwrap() == "gamedata")
             .map(|d| d.as_ref().parent().unwrap().to_path_buf())
@@ -240,6 +585,8 @@ impl Addons {
 pub struct FolderEntry {
     pub download: AddonKey,
     pub addon_folder: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<Hook>,
 }
 
 impl FolderEntry {
@@ -247,35 +594,18 @@ impl FolderEntry {
         Self {
             download: key,
             addon_folder: folder,
+            hooks: Vec::new(),
         }
     }
-}
-
-#[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Clone, Eq)]
-pub struct ModdbLink {
-    pub addon_link: String,
-    pub updated: String,
-}
 
-#[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Clone, Eq)]
-pub struct GithubLink {
-    pub repo: String,
-    pub tag: String,
-    pub filename: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Clone, Eq)]
-pub struct UrlLink {
-    url: String,
-}
-
-impl UrlLink {
-    pub fn new(url: String) -> Self {
-        Self { url }
-    }
-
-    pub fn get_download_url(&self) -> String {
-        self.url.clone()
+    // Hooks change files, so they run on a copy when the unpacked download must be kept
+    fn prepare(&self, dl_dir: &Path) -> Result<Option<TempDir>> {
+        if self.hooks.is_empty() {
+            return Ok(None);
+        }
+        let copy = copy_temporary(dl_dir)?;
+        Hook::apply_all(&self.hooks, copy.path())?;
+        Ok(Some(copy))
     }
 }
 
@@ -286,95 +616,40 @@ pub enum AddonKey {
     Moddb(ModdbLink),
     Github(GithubLink),
     Url(UrlLink),
+    Torrent(TorrentLink),
 }
 
-static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
-    reqwest::Client::builder()
-        .user_agent("Anomaly-Modder-Tool")
-        .build()
-        .unwrap()
-});
-
-static LINKS_REGEX: Lazy<Regex> = Lazy::new(|| regex::Regex::new("href=\"([^\"]*)\"").unwrap());
-const URL_MODDB: &str = "https://www.moddb.com/mods/stalker-anomaly/addons/";
-
-impl ModdbLink {
-    pub async fn get_download_url(&self) -> Result<String> {
-        let url = format!("{url}{addon}", url = URL_MODDB, addon = self.addon_link);
-        let resp = CLIENT.get(url).send().await?.text().await?;
-
-        let link = LINKS_REGEX
-            .captures_iter(&resp)
-            .map(|s| s.get(1).unwrap())
-            .find(|s| s.as_str().contains("addons/start"))
-            .map(|m| m.as_str().to_owned())
-            .ok_or_else(|| anyhow!("Couldn't find moddb download button"))?;
-
-        let resp = CLIENT
-            .get(format!("https://www.moddb.com{}", link))
-            .send()
-            .await?
-            .text()
-            .await?;
-        let link = LINKS_REGEX
-            .captures_iter(&resp)
-            .map(|s| s.get(1).unwrap())
-            .find(|s| s.as_str().contains("moddb.com/downloads/mirror"))
-            .map(|m| m.as_str().to_owned())
-            .ok_or_else(|| anyhow!("Couldn't find moddb mirror link"))?;
-        Ok(link)
-    }
-}
+impl AddonKey {
+    async fn download_link(&self) -> Result<String> {
+        use AddonKey::*;
 
-impl GithubLink {
-    async fn fetch_tag(&self) -> Result<Cow<str>> {
-        if self.tag != "latest" {
-            return Ok(Cow::Borrowed(&self.tag));
+        match self {
+            Moddb(link) => link.download_url().await,
+            Url(link) => link.download_url().await,
+            Github(link) => link.download_url().await,
+            Torrent(_) => bail!("Torrents can't be downloaded over http"),
         }
+    }
 
-        let resp = CLIENT
-            .head(format!(
-                r"https://github.com/{repo}/releases/latest",
-                repo = self.repo
-            ))
-            .send()
-            .await?;
+    pub fn source_url(&self) -> String {
+        use AddonKey::*;
 
-        if !resp.status().is_success() {
-            bail!("No such repo");
+        match self {
+            Moddb(link) => link.page_url(),
+            Url(link) => link.page_url(),
+            Github(link) => link.page_url(),
+            Torrent(link) => link.magnet.clone(),
         }
-
-        let last_segment = resp.url().path_segments().unwrap().last().unwrap();
-        let tag = match last_segment {
-            "releases" => bail!("No releases in repo"),
-            x => x,
-        };
-        Ok(Cow::Owned(tag.to_owned()))
-    }
-
-    pub async fn get_download_url(&self) -> Result<String> {
-        let tag = self.fetch_tag().await?;
-        let version = match tag.strip_prefix('v') {
-            // if tag starts with v (v3.2 for example), strips v
-            Some(v) => v,
-            None => &tag,
-        };
-        let filename = self.filename.replace("$VERSION", version);
-        Ok(format!(
-            "https://github.com/{repo}/releases/download/{tag}/{filename}",
-            repo = self.repo
-        ))
     }
-}
 
-impl AddonKey {
-    async fn download_link(&self) -> Result<impl IntoUrl> {
+    pub fn version(&self) -> Option<&str> {
         use AddonKey::*;
 
         match self {
-            Moddb(link) => link.get_download_url().await,
-            Url(link) => Ok(link.get_download_url()),
-            Github(link) => link.get_download_url().await,
+            Moddb(link) => Some(&link.updated),
+            Url(_) => None,
+            Github(link) => Some(&link.tag),
+            Torrent(_) => None,
         }
     }
 
@@ -398,27 +673,31 @@ mod tests {
 
     use tempfile::tempdir;
 
-    use crate::addonlist::GithubLink;
+    use crate::backup::Transaction;
+    use crate::config::UserOverrides;
+    use crate::hooks::Hook;
+    use crate::net::TorrentLink;
 
     use super::AddonKey;
     use super::Addons;
-    use super::CLIENT;
 
     use super::FolderEntry;
     use super::LoadOrder;
-    use super::ModdbLink;
+    use super::OrderRules;
+    use super::Mo2Plugins;
     use super::UrlLink;
 
-    #[tokio::test]
-    async fn moddb_link() {
-        let key = ModdbLink {
-            addon_link: "anomaly-mod-configuration-menu".to_owned(),
-            updated: "Aug 8th, 2022".to_owned(),
-        };
-        let url = key.get_download_url().await.unwrap();
-
-        let response = CLIENT.head(url).send().await.unwrap();
-        assert!(response.content_length().is_some()); // just check if it has size
+    #[test]
+    fn torrent_key() {
+        let json = r#"{"type":"torrent","magnet":"magnet:?xt=urn:btih:c9e15763f722f23e98a29decdfae341b98d53056"}"#;
+        let key: AddonKey = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            key,
+            AddonKey::Torrent(TorrentLink::new(
+                "magnet:?xt=urn:btih:c9e15763f722f23e98a29decdfae341b98d53056".to_owned()
+            ))
+        );
+        assert_eq!(serde_json::to_string(&key).unwrap(), json);
     }
 
     #[test]
@@ -461,7 +740,7 @@ mod tests {
         .map(Path::new)
         .into_iter();
 
-        let key = AddonKey::Url(UrlLink { url: "".to_owned() });
+        let key = AddonKey::Url(UrlLink::new("".to_owned()));
 
         let expected = [
             (
@@ -487,41 +766,9 @@ mod tests {
         );
     }
 
-    #[tokio::test]
-    async fn github_download_link_tagged() {
-        let key = GithubLink {
-            repo: "ModOrganizer2/modorganizer".to_owned(),
-            tag: "v2.4.3".to_owned(),
-            filename: "Mod.Organizer-$VERSION.7z".to_owned(),
-        };
-
-        let expected = "https://github.com/ModOrganizer2/modorganizer/releases/download/v2.4.3/Mod.Organizer-2.4.3.7z";
-        assert_eq!(key.get_download_url().await.unwrap(), expected);
-    }
-
-    #[tokio::test]
-    async fn github_download_link_latest() {
-        let key = GithubLink {
-            repo: "ModOrganizer2/modorganizer".to_owned(),
-            tag: "latest".to_owned(),
-            filename: "Mod.Organizer-$VERSION.7z".to_owned(),
-        };
-
-        let not_expected = "https://github.com/ModOrganizer2/modorganizer/releases/download/latest/Mod.Organizer-latest.7z";
-        let url = key.get_download_url().await.unwrap();
-        assert_ne!(url, not_expected);
-        assert!(CLIENT
-            .head(url)
-            .send()
-            .await
-            .unwrap()
-            .content_length()
-            .is_some());
-    }
-
     #[test]
     fn modorg_modlist() {
-        let entry = FolderEntry::new(AddonKey::from_url(UrlLink { url: "".to_owned() }), None);
+        let entry = FolderEntry::new(AddonKey::from_url(UrlLink::new("".to_owned())), None);
         let mut addons = Addons::new();
         addons.insert("community-task-pack".to_owned(), entry.clone());
         addons.insert("BaseGame_Task_Pack".to_owned(), entry.clone());
@@ -553,6 +800,140 @@ mod tests {
         assert!(modlist.to_modorg_modlist() == prefix);
     }
 
+    #[test]
+    fn modlist_round_trip() {
+        let mut order = LoadOrder::new();
+        for addon in ["community-task-pack", "Igigui", "Interactive_PDA"] {
+            order.push(addon.to_owned());
+        }
+
+        let modlist = order.to_modorg_modlist();
+        assert_eq!(LoadOrder::from_modorg_modlist(&modlist), order);
+        assert_eq!(LoadOrder::parse(&modlist), order);
+        assert_eq!(LoadOrder::parse(&order.to_plain_text()), order);
+
+        // straight from an MO2 profile
+        let profile = "# This file was automatically generated by Mod Organizer.\r\n\
+            +Interactive_PDA\r\n-Disabled_Addon\r\n+Tasks_separator\r\n+Igigui\r\n\
+            *DLC: Anomaly\r\n+community-task-pack\r\n";
+        assert_eq!(LoadOrder::parse(profile), order);
+    }
+
+    #[test]
+    fn import_order() {
+        let tmp = tempdir().unwrap();
+        let overrides = tmp.path().join("user_overrides.json");
+        LoadOrder::import("Igigui\nNot_In_Pack\n", None, &overrides, |_| false).unwrap();
+
+        let saved = UserOverrides::load(&overrides).unwrap();
+        assert_eq!(saved.load_order, ["Igigui", "Not_In_Pack"]);
+    }
+
+    #[test]
+    fn order_rules() {
+        let mut order = LoadOrder::new();
+        for addon in ["Patch_A", "Igigui", "Interactive_PDA", "Patch_B", "Arszi_Task_Pack"] {
+            order.push(addon.to_owned());
+        }
+        let rules: OrderRules = serde_json::from_str(
+            r#"{
+                "after": [
+                    { "addon": "Igigui", "after": "Arszi_Task_Pack" },
+                    { "addon": "Patch_B", "after": "Patch_A" },
+                    { "addon": "Igigui", "after": "Removed_Addon" }
+                ],
+                "groups": [
+                    { "name": "patches", "priority": "last", "addons": ["Patch_A", "Patch_B"] }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        order.resolve(&rules).unwrap();
+        assert_eq!(
+            order.as_ref(),
+            ["Interactive_PDA", "Arszi_Task_Pack", "Igigui", "Patch_A", "Patch_B"]
+        );
+
+        // resolving an already resolved order changes nothing
+        order.resolve(&rules).unwrap();
+        assert_eq!(order.as_ref()[2], "Igigui");
+
+        let cycle: OrderRules = serde_json::from_str(
+            r#"{ "after": [
+                { "addon": "Igigui", "after": "Interactive_PDA" },
+                { "addon": "Interactive_PDA", "after": "Igigui" }
+            ] }"#,
+        )
+        .unwrap();
+        assert!(order.resolve(&cycle).is_err());
+
+        let both: OrderRules = serde_json::from_str(
+            r#"{ "groups": [
+                { "name": "early", "priority": "first", "addons": ["Igigui"] },
+                { "name": "late", "priority": "last", "addons": ["Igigui"] }
+            ] }"#,
+        )
+        .unwrap();
+        assert!(order.resolve(&both).is_err());
+    }
+
+    #[test]
+    fn plugin_files() {
+        let entry = FolderEntry::new(AddonKey::from_url(UrlLink::new("".to_owned())), None);
+        let with_folder = FolderEntry::new(
+            AddonKey::from_url(UrlLink::new("".to_owned())),
+            Some("rootbuilder".to_owned()),
+        );
+
+        let over_root = tempdir().unwrap();
+        std::fs::create_dir_all(over_root.path().join("plugins/rootbuilder")).unwrap();
+        std::fs::File::create(over_root.path().join("plugins/rootbuilder/__init__.py")).unwrap();
+        let paths = Mo2Plugins::plugin_files(&entry, over_root.path())
+            .unwrap()
+            .relative_file_paths();
+        assert_eq!(paths, [PathBuf::from("rootbuilder/__init__.py")].into());
+
+        let nested = tempdir().unwrap();
+        std::fs::create_dir_all(nested.path().join("RootBuilder-4.5/rootbuilder")).unwrap();
+        std::fs::File::create(nested.path().join("RootBuilder-4.5/rootbuilder/__init__.py"))
+            .unwrap();
+        std::fs::File::create(nested.path().join("RootBuilder-4.5/readme.txt")).unwrap();
+        let paths = Mo2Plugins::plugin_files(&with_folder, nested.path())
+            .unwrap()
+            .relative_file_paths();
+        assert_eq!(paths, [PathBuf::from("rootbuilder/__init__.py")].into());
+    }
+
+    #[test]
+    fn hooked_install() {
+        let staged = tempdir().unwrap();
+        std::fs::create_dir_all(staged.path().join("Main Files/gamedata/gamedata")).unwrap();
+        std::fs::File::create(staged.path().join("Main Files/gamedata/gamedata/a.script"))
+            .unwrap();
+
+        let mut entry = FolderEntry::new(AddonKey::from_url(UrlLink::new("".to_owned())), None);
+        entry.hooks = vec![
+            Hook::Flatten {
+                folder: "Main Files/gamedata".to_owned(),
+            },
+            Hook::Flatten {
+                folder: "Main Files".to_owned(),
+            },
+        ];
+
+        let hooked = entry.prepare(staged.path()).unwrap().unwrap();
+        let paths = Addons::install(&entry, hooked.path())
+            .unwrap()
+            .relative_file_paths();
+        assert_eq!(paths, [PathBuf::from("gamedata/a.script")].into());
+        // the staged download is left untouched for the next addon or a resume
+        assert!(staged
+            .path()
+            .join("Main Files/gamedata/gamedata/a.script")
+            .is_file());
+    }
+
     #[test]
     fn missing_addons() {
         let dir = tempdir().unwrap();
@@ -560,7 +941,7 @@ mod tests {
         std::fs::create_dir(&mods_path).unwrap();
 
         let mut addons = Addons::new();
-        let entry = FolderEntry::new(AddonKey::from_url(UrlLink { url: "".to_owned() }), None);
+        let entry = FolderEntry::new(AddonKey::from_url(UrlLink::new("".to_owned())), None);
 
         let addons_found = [
             "community-task-pack".to_owned(),
@@ -579,7 +960,7 @@ mod tests {
             addons.insert(addon.clone(), entry.clone());
         }
 
-        let missing = addons.missing_addons(dir.path());
+        let missing = addons.missing_addons(&mods_path);
         assert_eq!(missing.len(), addons_missing.len());
         for addon in &addons_missing {
             assert!(missing.contains(&addon.as_str()));
@@ -1,10 +1,26 @@
 use anyhow::Result;
 use egui::output::OpenUrl;
 use parking_lot::Mutex;
-use std::{path::PathBuf, sync::Arc, thread::JoinHandle};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::JoinHandle,
+};
 
-use crate::actions::{
-    download_7zip, AppAction, InstallMo2, InstallMo2Progress, InstallModdedExes, Unpacker7Zip,
+use crate::{
+    actions::{
+        addon_roots, download_7zip, list_archive, list_zip, AppAction, ArchiveEntry, InstallMo2,
+        InstallMo2Progress, InstallModdedExes, Unpacker7Zip,
+    },
+    config::{ModpackConfig, BUNDLED_CONFIG, USER_OVERRIDES},
+    mo2::Mo2Instance,
+    addonlist::{LoadOrder, Modpack},
+    net::client,
+    processes::running_blockers,
+    settings::{ProxySettings, Settings, SETTINGS, SETTINGS_FILE},
+    stats::{human_size, Cleanup, DiskUsage},
+    status::HealthReport,
+    updates::{spawn_checker, Update},
 };
 
 enum AppState {
@@ -13,6 +29,125 @@ enum AppState {
     Normal,
     InstallMo2(Operation<InstallMo2>),
     InstallModdedExes(Operation<InstallModdedExes>),
+    Settings(Settings, Option<String>),
+    HealthCheck(String),
+    Failed(String),
+    Archives(ArchivesView),
+    Stats(StatsView),
+    Order(LoadOrderView),
+    CloseProcesses(Vec<String>, BlockedAction),
+}
+
+struct LoadOrderView {
+    text: String,
+    message: Option<String>,
+}
+
+struct StatsView {
+    usage: Result<DiskUsage, String>,
+    confirm: Option<Cleanup>,
+    error: Option<String>,
+}
+
+impl StatsView {
+    fn new(app_ctx: &AppContext) -> Self {
+        let usage = match &app_ctx.mo2 {
+            Some(mo2) => DiskUsage::collect(&app_ctx.anomaly_dir, mo2).map_err(|e| e.to_string()),
+            None => Err("MO2 is not installed".to_owned()),
+        };
+        Self {
+            usage,
+            confirm: None,
+            error: None,
+        }
+    }
+}
+
+struct ArchivesView {
+    archives: Vec<PathBuf>,
+    preview: Option<(PathBuf, Result<Vec<ArchiveEntry>, String>)>,
+}
+
+// Installs that copy into mo2/ or the game folder, they fail on files held by MO2 or the game.
+// A load order import is lost when MO2 rewrites modlist.txt on exit
+#[derive(Clone)]
+enum BlockedAction {
+    InstallMo2,
+    InstallModdedExes,
+    ImportOrder(String),
+}
+
+impl BlockedAction {
+    fn start_when_closed(self, ctx: &egui::Context, app_ctx: Arc<AppContext>) -> AppState {
+        match running_blockers() {
+            Ok(running) if !running.is_empty() => AppState::CloseProcesses(running, self),
+            Ok(_) => self.start(ctx, app_ctx),
+            Err(e) => {
+                println!("Can't check running programs: {}", e);
+                self.start(ctx, app_ctx)
+            }
+        }
+    }
+
+    fn start(self, ctx: &egui::Context, app_ctx: Arc<AppContext>) -> AppState {
+        let gui_ctx = ctx.clone();
+        match self {
+            BlockedAction::InstallMo2 => {
+                let progress = Arc::new(Mutex::new(InstallMo2Progress::default()));
+                let progress_cl = progress.clone();
+                let plugins = match ModpackConfig::load(BUNDLED_CONFIG, Path::new(USER_OVERRIDES)) {
+                    Ok(config) => config.mo2_plugins,
+                    Err(e) => return AppState::Failed(format!("Can't read the pack: {:#}", e)),
+                };
+                let handle = std::thread::spawn(move || {
+                    InstallMo2::run(plugins, app_ctx, |p| {
+                        *progress_cl.lock() = p.clone();
+                        gui_ctx.request_repaint();
+                    })
+                });
+                AppState::InstallMo2(Operation::<InstallMo2> { handle, progress })
+            }
+            BlockedAction::InstallModdedExes => {
+                let handle = std::thread::spawn(move || {
+                    InstallModdedExes::run((), app_ctx, |_| {
+                        gui_ctx.request_repaint();
+                    })
+                });
+                AppState::InstallModdedExes(Operation::<InstallModdedExes> {
+                    handle,
+                    progress: Arc::new(Mutex::new(())),
+                })
+            }
+            BlockedAction::ImportOrder(text) => {
+                let imported = LoadOrder::import(
+                    &text,
+                    app_ctx.mo2.as_ref(),
+                    Path::new(USER_OVERRIDES),
+                    // checked right before, only something started in between gets here
+                    |_| false,
+                );
+                let message = match imported {
+                    Ok(()) => "Imported".to_owned(),
+                    Err(e) => format!("Import failed: {}", e),
+                };
+                AppState::Order(LoadOrderView {
+                    text,
+                    message: Some(message),
+                })
+            }
+        }
+    }
+
+    fn cancel(self) -> AppState {
+        match self {
+            // keep what was pasted
+            BlockedAction::ImportOrder(text) => AppState::Order(LoadOrderView {
+                text,
+                message: None,
+            }),
+            _ => AppState::Normal,
+        }
+    }
 }
 
 trait Gui {
@@ -24,6 +159,92 @@ trait Gui {
     ) -> Option<AppState>;
 }
 
+// Arrow keys (the Deck's d-pad under Steam Input) move focus between these widgets,
+// Enter or Space presses the focused one. The flag marks widgets that give left and right
+// to the focus ring, sliders and number fields keep them for their value, single-line text
+// fields for the caret. Multiline text stays out of the ring and keeps all arrow keys.
+fn focus_ring() -> egui::Id {
+    egui::Id::new("amt_focus_ring")
+}
+
+fn nav_widget(
+    ui: &mut egui::Ui,
+    enabled: bool,
+    widget: impl egui::Widget,
+    horizontal: bool,
+) -> egui::Response {
+    let response = ui.add_enabled(enabled, widget);
+    if enabled {
+        ui.data()
+            .get_temp_mut_or_default::<Vec<(egui::Id, bool)>>(focus_ring())
+            .push((response.id, horizontal));
+    }
+    response
+}
+
+fn nav_button(
+    ui: &mut egui::Ui,
+    enabled: bool,
+    text: impl Into<egui::WidgetText>,
+) -> egui::Response {
+    nav_widget(ui, enabled, egui::Button::new(text), true)
+}
+
+fn nav_checkbox(
+    ui: &mut egui::Ui,
+    checked: &mut bool,
+    text: impl Into<egui::WidgetText>,
+) -> egui::Response {
+    nav_widget(ui, true, egui::Checkbox::new(checked, text), true)
+}
+
+fn nav_slider(ui: &mut egui::Ui, enabled: bool, slider: egui::Slider) -> egui::Response {
+    nav_widget(ui, enabled, slider, false)
+}
+
+// Runs before painting with the previous frame's ring. The key that moves focus is consumed,
+// otherwise a number field would also step its value on the way out.
+fn navigate_focus(ctx: &egui::Context) {
+    let ring = ctx
+        .data()
+        .get_temp::<Vec<(egui::Id, bool)>>(focus_ring())
+        .unwrap_or_default();
+    if ring.is_empty() {
+        return;
+    }
+    let focused = match ctx.memory().focus() {
+        // multiline text fields keep their arrow keys
+        Some(id) => match ring.iter().position(|(r, _)| *r == id) {
+            Some(ix) => Some(ix),
+            None => return,
+        },
+        None => None,
+    };
+
+    let horizontal = focused.is_none_or(|ix| ring[ix].1);
+    let keys = [
+        (egui::Key::ArrowDown, 1, false),
+        (egui::Key::ArrowUp, -1, false),
+        (egui::Key::ArrowRight, 1, true),
+        (egui::Key::ArrowLeft, -1, true),
+    ];
+    let step: isize = match keys
+        .into_iter()
+        .filter(|(_, _, h)| horizontal || !h)
+        .find(|(key, _, _)| ctx.input_mut().consume_key(egui::Modifiers::NONE, *key))
+    {
+        Some((_, step, _)) => step,
+        None => return,
+    };
+
+    let next = match focused {
+        Some(ix) => (ix as isize + step).rem_euclid(ring.len() as isize) as usize,
+        None if step > 0 => 0,
+        None => ring.len() - 1,
+    };
+    ctx.memory().request_focus(ring[next].0);
+}
+
 struct Operation<T: AppAction> {
     handle: JoinHandle<Result<T::Output>>,
     progress: Arc<Mutex<T::Progress>>,
@@ -74,10 +295,21 @@ impl Gui for Operation<InstallMo2> {
             }
         };
 
+        let plugins_progress = |ui: &mut egui::Ui| {
+            if let Some(x) = lock.plugins_done {
+                ui.label(if x {
+                    "Installing plugins... Done."
+                } else {
+                    "Installing plugins..."
+                });
+            }
+        };
+
         egui::CentralPanel::default().show(ctx, |ui| {
             download_progress(ui);
             unzip_progress(ui);
             configure_progress(ui);
+            plugins_progress(ui);
             egui::warn_if_debug_build(ui);
         });
         if lock.finished {
@@ -85,7 +317,7 @@ impl Gui for Operation<InstallMo2> {
                 .auto_sized()
                 .show(ctx, |ui| {
                     ui.heading("All good! Done.");
-                    if ui.button("Okay ^^").clicked() {
+                    if nav_button(ui, true, "Okay ^^").clicked() {
                         return Some(AppState::Normal);
                     }
                     None
@@ -115,13 +347,15 @@ impl Gui for Operation<InstallModdedExes> {
 
 pub struct AppContext {
     pub anomaly_dir: PathBuf,
-    pub mo_dir: Option<PathBuf>,
+    pub mo2: Option<Mo2Instance>,
     pub unpacker_7zip: Option<Unpacker7Zip<tempfile::TempPath>>,
+    pub updates: Mutex<Vec<Update>>,
 }
 
 pub struct TemplateApp {
     state: AppState,
     context: Arc<AppContext>,
+    layout: Option<(bool, Option<f32>)>,
 }
 
 impl Default for TemplateApp {
@@ -129,20 +363,17 @@ impl Default for TemplateApp {
         let anomaly_dir = std::env::current_dir().unwrap();
         let runtime = tokio::runtime::Runtime::new().unwrap();
         let unpacker_7zip = runtime.block_on(download_7zip()).ok();
-        let mo_dir = std::path::Path::new("mo2");
 
         let anomaly_exists = anomaly_dir.join("AnomalyLauncher.exe").is_file();
         let game_initialized = anomaly_dir.join("appdata\\user.ltx").is_file();
         Self {
             context: Arc::new(AppContext {
-                mo_dir: if mo_dir.exists() {
-                    Some(mo_dir.to_path_buf())
-                } else {
-                    None
-                },
+                mo2: Mo2Instance::detect(&anomaly_dir).ok().flatten(),
                 anomaly_dir,
                 unpacker_7zip,
+                updates: Mutex::new(Vec::new()),
             }),
+            layout: None,
             state: if !anomaly_exists {
                 AppState::NoAnomaly
             } else if !game_initialized {
@@ -155,8 +386,11 @@ impl Default for TemplateApp {
 }
 
 impl TemplateApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Default::default()
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let app = Self::default();
+        let egui_ctx = cc.egui_ctx.clone();
+        spawn_checker(app.context.clone(), move || egui_ctx.request_repaint());
+        app
     }
 
     fn paint_game_not_initialized(
@@ -187,10 +421,7 @@ impl TemplateApp {
         app_ctx: Arc<AppContext>,
     ) -> Option<AppState> {
         let book_button = |ui: &mut egui::Ui| -> Option<AppState> {
-            if ui
-                .add_enabled(input_enabled, egui::Button::new("Modding Book"))
-                .clicked()
-            {
+            if nav_button(ui, input_enabled, "Modding Book").clicked() {
                 ctx.output().open_url = Some(OpenUrl::new_tab(
                     "https://igigog.github.io/anomaly-modding-book/",
                 ));
@@ -199,65 +430,154 @@ impl TemplateApp {
         };
 
         let mo2_button = |ui: &mut egui::Ui| -> Option<AppState> {
-            if !ui
-                .add_enabled(input_enabled, egui::Button::new("Install MO2"))
-                .clicked()
-            {
+            if !nav_button(ui, input_enabled, "Install MO2").clicked() {
                 return None;
             };
+            Some(BlockedAction::InstallMo2.start_when_closed(ctx, app_ctx.clone()))
+        };
 
-            let app_ctx = app_ctx.clone();
-            let gui_ctx = ctx.clone();
-            let progress = Arc::new(Mutex::new(InstallMo2Progress::default()));
-            let progress_cl = progress.clone();
-            let handle = std::thread::spawn(move || {
-                InstallMo2::run((), app_ctx, |p| {
-                    *progress_cl.lock() = p.clone();
-                    gui_ctx.request_repaint();
-                })
-            });
-            Some(AppState::InstallMo2(Operation::<InstallMo2> {
-                handle,
-                progress,
+        let modded_exes_button = |ui: &mut egui::Ui| {
+            if !nav_button(ui, input_enabled, "Install Modded Exes").clicked() {
+                return None;
+            };
+            Some(BlockedAction::InstallModdedExes.start_when_closed(ctx, app_ctx.clone()))
+        };
+
+        let settings_button = |ui: &mut egui::Ui| {
+            if !nav_button(ui, input_enabled, "Settings").clicked() {
+                return None;
+            };
+            Some(AppState::Settings(SETTINGS.read().clone(), None))
+        };
+
+        let health_button = |ui: &mut egui::Ui| {
+            if !nav_button(ui, input_enabled, "Health check").clicked() {
+                return None;
+            };
+            let report = ModpackConfig::load(BUNDLED_CONFIG, Path::new(USER_OVERRIDES))
+                .and_then(Modpack::try_from)
+                .and_then(|pack| HealthReport::collect(&app_ctx.anomaly_dir, &pack))
+                .map(|r| r.to_string())
+                .unwrap_or_else(|e| format!("Health check failed: {}", e));
+            Some(AppState::HealthCheck(report))
+        };
+
+        let archives_button = |ui: &mut egui::Ui| {
+            let Some(mo2) = &app_ctx.mo2 else {
+                return None;
+            };
+            if !nav_button(ui, input_enabled, "Archives").clicked() {
+                return None;
+            };
+            let mut archives: Vec<PathBuf> = std::fs::read_dir(&mo2.downloads_dir)
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_file() && p.extension().is_none_or(|e| e != "meta"))
+                .collect();
+            archives.sort();
+            Some(AppState::Archives(ArchivesView {
+                archives,
+                preview: None,
             }))
         };
 
-        let modded_exes_button = |ui: &mut egui::Ui| {
-            if !ui
-                .add_enabled(input_enabled, egui::Button::new("Install Modded Exes"))
-                .clicked()
-            {
+        let stats_button = |ui: &mut egui::Ui| {
+            if !nav_button(ui, input_enabled, "Disk usage").clicked() {
                 return None;
             };
+            Some(AppState::Stats(StatsView::new(&app_ctx)))
+        };
 
-            let app_ctx = app_ctx.clone();
-            let gui_ctx = ctx.clone();
-            let handle = std::thread::spawn(move || {
-                InstallModdedExes::run((), app_ctx, |p| {
-                    gui_ctx.request_repaint();
-                })
-            });
-            Some(AppState::InstallModdedExes(
-                Operation::<InstallModdedExes> {
-                    handle,
-                    progress: Arc::new(Mutex::new(())),
+        let order_button = |ui: &mut egui::Ui| {
+            if !nav_button(ui, input_enabled, "Load order").clicked() {
+                return None;
+            };
+            let text = ModpackConfig::load(BUNDLED_CONFIG, Path::new(USER_OVERRIDES))
+                .and_then(Modpack::try_from)
+                .map(|pack| pack.order().to_plain_text());
+            Some(AppState::Order(match text {
+                Ok(text) => LoadOrderView {
+                    text,
+                    message: None,
                 },
-            ))
+                Err(e) => LoadOrderView {
+                    text: String::new(),
+                    message: Some(format!("Can't read the pack: {}", e)),
+                },
+            }))
+        };
+
+        let updates_badge = |ui: &mut egui::Ui| {
+            let updates = app_ctx.updates.lock();
+            if updates.is_empty() {
+                return;
+            }
+            let list = updates
+                .iter()
+                .map(|u| u.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            ui.separator();
+            ui.small(format!("Updates available: {}", updates.len()))
+                .on_hover_text(list);
         };
 
+        let buttons = |ui: &mut egui::Ui| {
+            book_button(ui);
+            let mo_state = mo2_button(ui);
+            let exes_state = modded_exes_button(ui);
+            let health_state = health_button(ui);
+            let archives_state = archives_button(ui);
+            let stats_state = stats_button(ui);
+            let order_state = order_button(ui);
+            let settings_state = settings_button(ui);
+            updates_badge(ui);
+            mo_state
+                .or(exes_state)
+                .or(health_state)
+                .or(archives_state)
+                .or(stats_state)
+                .or(order_state)
+                .or(settings_state)
+        };
+
+        // on a 1280x800 screen the side panel eats too much, buttons go on top instead
+        if SETTINGS.read().compact_layout {
+            return egui::TopBottomPanel::top("top_panel")
+                .show(ctx, |ui| ui.horizontal_wrapped(buttons).inner)
+                .inner;
+        }
         egui::SidePanel::left("side_panel")
             .show(ctx, |ui| {
-                ui.with_layout(egui::Layout::top_down_justified(egui::Align::TOP), |ui| {
-                    book_button(ui);
-                    let mo_state = mo2_button(ui);
-                    let exes_state = modded_exes_button(ui);
-                    mo_state.or(exes_state)
-                })
-                .inner
+                ui.with_layout(egui::Layout::top_down_justified(egui::Align::TOP), buttons)
+                    .inner
             })
             .inner
     }
 
+    fn apply_layout(ctx: &egui::Context, frame: &eframe::Frame, compact: bool, scale: Option<f32>) {
+        // keep the visuals eframe picked, only the spacing changes
+        let mut style = (*ctx.style()).clone();
+        let mut spacing = egui::style::Spacing::default();
+        if compact {
+            // finger-sized targets
+            spacing.interact_size.y = 40.0;
+            spacing.button_padding = egui::vec2(14.0, 10.0);
+            spacing.item_spacing = egui::vec2(10.0, 10.0);
+            spacing.scroll_bar_width = 16.0;
+        }
+        style.spacing.interact_size = spacing.interact_size;
+        style.spacing.button_padding = spacing.button_padding;
+        style.spacing.item_spacing = spacing.item_spacing;
+        style.spacing.scroll_bar_width = spacing.scroll_bar_width;
+        ctx.set_style(style);
+        ctx.set_pixels_per_point(
+            scale.unwrap_or_else(|| frame.info().native_pixels_per_point.unwrap_or(1.0)),
+        );
+    }
+
     fn paint_normal(ctx: &egui::Context, app_ctx: Arc<AppContext>) -> Option<AppState> {
         if let Some(s) = Self::paint_secondary_panels(ctx, true, app_ctx) {
             return Some(s);
@@ -266,10 +586,443 @@ impl TemplateApp {
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
             ui.heading("Привет мой гуй! ^^");
+            if let Err(e) = client() {
+                ui.colored_label(egui::Color32::RED, format!("{:#}", e));
+            }
             egui::warn_if_debug_build(ui);
         });
         None
     }
+
+    fn paint_health_check(
+        ctx: &egui::Context,
+        report: &str,
+        app_ctx: Arc<AppContext>,
+    ) -> Option<AppState> {
+        if let Some(s) = Self::paint_secondary_panels(ctx, true, app_ctx) {
+            return Some(s);
+        }
+
+        egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                ui.heading("Health check");
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.monospace(report);
+                });
+                if nav_button(ui, true, "Back").clicked() {
+                    return Some(AppState::Normal);
+                }
+                None
+            })
+            .inner
+    }
+
+    fn paint_failed(
+        ctx: &egui::Context,
+        message: &str,
+        app_ctx: Arc<AppContext>,
+    ) -> Option<AppState> {
+        if let Some(s) = Self::paint_secondary_panels(ctx, true, app_ctx) {
+            return Some(s);
+        }
+
+        egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                ui.heading("Something went wrong");
+                ui.label(message);
+                if nav_button(ui, true, "Back").clicked() {
+                    return Some(AppState::Normal);
+                }
+                None
+            })
+            .inner
+    }
+
+    fn paint_close_processes(
+        ctx: &egui::Context,
+        running: &[String],
+        action: &BlockedAction,
+        app_ctx: Arc<AppContext>,
+    ) -> Option<AppState> {
+        if let Some(s) = Self::paint_secondary_panels(ctx, false, app_ctx.clone()) {
+            return Some(s);
+        }
+
+        egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                ui.heading("Close running programs");
+                ui.label("These programs hold files that are about to be replaced:");
+                for name in running {
+                    ui.monospace(name);
+                }
+                ui.label("Close them and check again.");
+                if nav_button(ui, true, "Check again").clicked() {
+                    return Some(action.clone().start_when_closed(ctx, app_ctx));
+                }
+                if nav_button(ui, true, "Cancel").clicked() {
+                    return Some(action.clone().cancel());
+                }
+                None
+            })
+            .inner
+    }
+
+    fn paint_archives(
+        ctx: &egui::Context,
+        view: &mut ArchivesView,
+        app_ctx: Arc<AppContext>,
+    ) -> Option<AppState> {
+        if let Some(s) = Self::paint_secondary_panels(ctx, true, app_ctx.clone()) {
+            return Some(s);
+        }
+
+        let next = egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                ui.heading("Downloaded archives");
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for archive in &view.archives {
+                        let name = archive.file_name().unwrap().to_string_lossy();
+                        if nav_button(ui, true, name).clicked() {
+                            let listing = match &app_ctx.unpacker_7zip {
+                                Some(unpacker) => list_archive(unpacker, archive),
+                                None => list_zip(archive),
+                            };
+                            view.preview =
+                                Some((archive.clone(), listing.map_err(|e| e.to_string())));
+                        }
+                    }
+                });
+                if nav_button(ui, true, "Back").clicked() {
+                    return Some(AppState::Normal);
+                }
+                None
+            })
+            .inner;
+
+        if let Some((archive, listing)) = &view.preview {
+            let mut open = true;
+            egui::Window::new(archive.file_name().unwrap().to_string_lossy())
+                .open(&mut open)
+                .show(ctx, |ui| match listing {
+                    Ok(entries) => {
+                        let roots = addon_roots(entries)
+                            .iter()
+                            .map(|r| match r.to_string_lossy() {
+                                r if r.is_empty() => "<archive root>".to_owned(),
+                                r => r.into_owned(),
+                            })
+                            .collect::<Vec<_>>();
+                        ui.label(if roots.is_empty() {
+                            "No gamedata folder, not an addon archive?".to_owned()
+                        } else {
+                            format!("Addon folders: {}", roots.join(", "))
+                        });
+                        ui.separator();
+                        egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                            for entry in entries.iter().filter(|e| !e.is_dir) {
+                                ui.monospace(format!(
+                                    "{:>10.1} KiB  {}",
+                                    entry.size as f64 / 1024.0,
+                                    entry.path.display()
+                                ));
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        ui.label(format!("Can't read the archive: {}", e));
+                    }
+                });
+            if !open {
+                view.preview = None;
+            }
+        }
+        next
+    }
+
+    fn paint_stats(
+        ctx: &egui::Context,
+        view: &mut StatsView,
+        app_ctx: Arc<AppContext>,
+    ) -> Option<AppState> {
+        if let Some(s) = Self::paint_secondary_panels(ctx, true, app_ctx.clone()) {
+            return Some(s);
+        }
+
+        let next = egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                ui.heading("Disk usage");
+                let usage = match &view.usage {
+                    Ok(usage) => usage,
+                    Err(e) => {
+                        ui.label(format!("Can't collect statistics: {}", e));
+                        return nav_button(ui, true, "Back")
+                            .clicked()
+                            .then_some(AppState::Normal);
+                    }
+                };
+                if let Some(error) = &view.error {
+                    ui.colored_label(egui::Color32::RED, error.as_str());
+                }
+
+                egui::Grid::new("disk_totals").show(ui, |ui| {
+                    let mut total = |ui: &mut egui::Ui, label: &str, size: u64, cleanup| {
+                        ui.label(label);
+                        ui.label(human_size(size));
+                        if let Some(cleanup) = cleanup {
+                            if nav_button(ui, size > 0, "Clean up").clicked() {
+                                view.confirm = Some(cleanup);
+                            }
+                        }
+                        ui.end_row();
+                    };
+                    total(ui, "Addons", usage.addons_total(), None);
+                    total(ui, "Download cache", usage.downloads, Some(Cleanup::Downloads));
+                    total(ui, "Backups", usage.backups, Some(Cleanup::Backups));
+                    total(ui, "Unfinished install", usage.staging, Some(Cleanup::Staging));
+                });
+
+                ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("addon_sizes").striped(true).show(ui, |ui| {
+                        ui.strong("Addon");
+                        ui.strong("Installed");
+                        ui.strong("Archive");
+                        ui.end_row();
+                        for (addon, stats) in &usage.addons {
+                            ui.label(addon);
+                            ui.label(human_size(stats.unpacked_size));
+                            ui.label(stats.archive_size.map(human_size).unwrap_or_default());
+                            ui.end_row();
+                        }
+                    });
+                });
+                nav_button(ui, true, "Back")
+                    .clicked()
+                    .then_some(AppState::Normal)
+            })
+            .inner;
+
+        if let (Some(cleanup), Some(mo2)) = (view.confirm, &app_ctx.mo2) {
+            let warning = match cleanup {
+                Cleanup::Downloads => "Delete archives this tool put into MO2 downloads?",
+                Cleanup::Backups => "Delete backups? Vanilla exes can't be restored after this.",
+                Cleanup::Staging => "Delete downloads of the unfinished install?",
+            };
+            egui::Window::new("Clean up")
+                .collapsible(false)
+                .auto_sized()
+                .show(ctx, |ui| {
+                    ui.label(warning);
+                    ui.horizontal(|ui| {
+                        if nav_button(ui, true, "Delete").clicked() {
+                            let result = cleanup.run(&app_ctx.anomaly_dir, mo2);
+                            // refreshed either way, a failed cleanup may have deleted some of it
+                            *view = StatsView::new(&app_ctx);
+                            view.error = result.err().map(|e| format!("Cleanup failed: {:#}", e));
+                        }
+                        if nav_button(ui, true, "Cancel").clicked() {
+                            view.confirm = None;
+                        }
+                    });
+                });
+        }
+        next
+    }
+
+    // Pasted text replaces the pack order and is kept in the user overrides
+    fn paint_load_order(
+        ctx: &egui::Context,
+        view: &mut LoadOrderView,
+        app_ctx: Arc<AppContext>,
+    ) -> Option<AppState> {
+        if let Some(s) = Self::paint_secondary_panels(ctx, true, app_ctx.clone()) {
+            return Some(s);
+        }
+
+        egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                ui.heading("Load order");
+                ui.label(
+                    "Lowest priority first. Paste a shared list or a modlist.txt to import it.",
+                );
+                ui.horizontal(|ui| {
+                    if nav_button(ui, true, "Copy as text").clicked() {
+                        let order = LoadOrder::parse(&view.text);
+                        ctx.output().copied_text = order.to_plain_text();
+                    }
+                    if nav_button(ui, true, "Copy as modlist.txt").clicked() {
+                        let order = LoadOrder::parse(&view.text);
+                        ctx.output().copied_text = order.to_modorg_modlist();
+                    }
+                    if nav_button(ui, true, "Import").clicked() {
+                        let action = BlockedAction::ImportOrder(view.text.clone());
+                        return Some(action.start_when_closed(ctx, app_ctx.clone()));
+                    }
+                    if nav_button(ui, true, "Back").clicked() {
+                        return Some(AppState::Normal);
+                    }
+                    None
+                })
+                .inner
+                .or_else(|| {
+                    if let Some(message) = &view.message {
+                        ui.label(message);
+                    }
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.add(
+                            egui::TextEdit::multiline(&mut view.text)
+                                .code_editor()
+                                .desired_width(f32::INFINITY),
+                        );
+                    });
+                    None
+                })
+            })
+            .inner
+    }
+
+    fn paint_settings(
+        ctx: &egui::Context,
+        draft: &mut Settings,
+        error: &mut Option<String>,
+        app_ctx: Arc<AppContext>,
+    ) -> Option<AppState> {
+        Self::paint_secondary_panels(ctx, false, app_ctx);
+
+        let speed_limit = |ui: &mut egui::Ui, label: &str, limit: &mut Option<u32>| {
+            ui.horizontal(|ui| {
+                let mut enabled = limit.is_some();
+                let mut value = limit.unwrap_or(1024);
+                nav_checkbox(ui, &mut enabled, label);
+                nav_widget(
+                    ui,
+                    enabled,
+                    egui::DragValue::new(&mut value)
+                        .clamp_range(1..=u32::MAX)
+                        .suffix(" KiB/s"),
+                    false,
+                );
+                *limit = enabled.then_some(value);
+            });
+        };
+
+        egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                ui.heading("Settings");
+                speed_limit(ui, "Limit total download speed", &mut draft.download_limit);
+                speed_limit(ui, "Limit speed per download", &mut draft.connection_limit);
+
+                ui.separator();
+                let mut use_proxy = draft.proxy.is_some();
+                nav_checkbox(ui, &mut use_proxy, "Use proxy");
+                match (use_proxy, &mut draft.proxy) {
+                    (true, Some(proxy)) => {
+                        egui::Grid::new("proxy_settings").show(ui, |ui| {
+                            ui.label("Url");
+                            nav_widget(
+                                ui,
+                                true,
+                                egui::TextEdit::singleline(&mut proxy.url)
+                                    .hint_text("socks5://127.0.0.1:1080"),
+                                false,
+                            );
+                            ui.end_row();
+                            ui.label("Username");
+                            nav_widget(
+                                ui,
+                                true,
+                                egui::TextEdit::singleline(
+                                    proxy.username.get_or_insert_with(String::new),
+                                ),
+                                false,
+                            );
+                            ui.end_row();
+                            ui.label("Password");
+                            nav_widget(
+                                ui,
+                                true,
+                                egui::TextEdit::singleline(
+                                    proxy.password.get_or_insert_with(String::new),
+                                )
+                                .password(true),
+                                false,
+                            );
+                            ui.end_row();
+                        });
+                    }
+                    (true, None) => draft.proxy = Some(ProxySettings::default()),
+                    (false, _) => draft.proxy = None,
+                }
+
+                ui.label("Extra CA certificates (PEM files, one per line)");
+                let mut certs = draft
+                    .extra_ca_certs
+                    .iter()
+                    .map(|p| p.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if ui.text_edit_multiline(&mut certs).changed() {
+                    draft.extra_ca_certs = certs.split('\n').map(PathBuf::from).collect();
+                }
+                ui.label("Network settings are applied after restart.");
+                ui.label("Torrent peers and DHT connect directly unless the proxy is socks5.");
+
+                ui.separator();
+                nav_checkbox(ui, &mut draft.compact_layout, "Compact layout (Steam Deck)");
+                ui.horizontal(|ui| {
+                    let mut enabled = draft.ui_scale.is_some();
+                    let mut scale = draft.ui_scale.unwrap_or(1.5);
+                    nav_checkbox(ui, &mut enabled, "UI scale");
+                    nav_slider(ui, enabled, egui::Slider::new(&mut scale, 0.75..=3.0));
+                    draft.ui_scale = enabled.then_some(scale);
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let mut enabled = draft.update_interval.is_some();
+                    let mut hours = draft.update_interval.unwrap_or(24);
+                    nav_checkbox(ui, &mut enabled, "Check for updates every");
+                    nav_widget(
+                        ui,
+                        enabled,
+                        egui::DragValue::new(&mut hours)
+                            .clamp_range(1..=24 * 30)
+                            .suffix(" h"),
+                        false,
+                    );
+                    draft.update_interval = enabled.then_some(hours);
+                });
+
+                if let Some(error) = error {
+                    ui.colored_label(egui::Color32::RED, error.as_str());
+                }
+                ui.horizontal(|ui| {
+                    if nav_button(ui, true, "Save").clicked() {
+                        draft.extra_ca_certs.retain(|p| !p.as_os_str().is_empty());
+                        if let Some(proxy) = &mut draft.proxy {
+                            proxy.username = proxy.username.take().filter(|u| !u.is_empty());
+                            proxy.password = proxy.password.take().filter(|p| !p.is_empty());
+                        }
+                        match draft
+                            .http_client()
+                            .and_then(|_| draft.save(Path::new(SETTINGS_FILE)))
+                        {
+                            Ok(()) => {
+                                *SETTINGS.write() = draft.clone();
+                                return Some(AppState::Normal);
+                            }
+                            Err(e) => *error = Some(format!("Can't save settings: {:#}", e)),
+                        }
+                    }
+                    if nav_button(ui, true, "Cancel").clicked() {
+                        return Some(AppState::Normal);
+                    }
+                    None
+                })
+                .inner
+            })
+            .inner
+    }
 }
 
 impl eframe::App for TemplateApp {
@@ -278,12 +1031,33 @@ impl eframe::App for TemplateApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         use AppState::*;
 
-        let next_state = match &self.state {
+        let layout = {
+            let settings = SETTINGS.read();
+            (settings.compact_layout, settings.ui_scale)
+        };
+        if self.layout != Some(layout) {
+            Self::apply_layout(ctx, frame, layout.0, layout.1);
+            self.layout = Some(layout);
+        }
+        navigate_focus(ctx);
+        ctx.data()
+            .insert_temp(focus_ring(), Vec::<(egui::Id, bool)>::new());
+
+        let next_state = match &mut self.state {
             NoAnomaly => self.paint_no_game(ctx, frame),
             GameNotInitialized => self.paint_game_not_initialized(ctx, frame),
             Normal => Self::paint_normal(ctx, self.context.clone()),
             InstallMo2(op) => op.paint(ctx, frame, self.context.clone()),
             InstallModdedExes(op) => op.paint(ctx, frame, self.context.clone()),
+            Settings(draft, error) => Self::paint_settings(ctx, draft, error, self.context.clone()),
+            HealthCheck(report) => Self::paint_health_check(ctx, report, self.context.clone()),
+            Failed(message) => Self::paint_failed(ctx, message, self.context.clone()),
+            Archives(view) => Self::paint_archives(ctx, view, self.context.clone()),
+            Stats(view) => Self::paint_stats(ctx, view, self.context.clone()),
+            Order(view) => Self::paint_load_order(ctx, view, self.context.clone()),
+            CloseProcesses(running, action) => {
+                Self::paint_close_processes(ctx, running, action, self.context.clone())
+            }
         };
         if let Some(s) = next_state {
             self.state = s;
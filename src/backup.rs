@@ -3,9 +3,67 @@ use std::{
     collections::HashSet,
     io::ErrorKind,
     path::{Path, PathBuf},
+    time::Duration,
     vec::IntoIter,
 };
 
+// ~6s in total, enough for an antivirus scan or MO2 letting go of a file
+static LOCK_RETRIES: &[Duration] = &[
+    Duration::from_millis(100),
+    Duration::from_millis(200),
+    Duration::from_millis(400),
+    Duration::from_millis(800),
+    Duration::from_millis(1600),
+    Duration::from_millis(3200),
+];
+
+// ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+#[cfg(windows)]
+fn is_locked(e: &std::io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(32) | Some(33))
+}
+
+// other systems don't lock open files, 32 is EPIPE on Linux
+#[cfg(not(windows))]
+fn is_locked(_e: &std::io::Error) -> bool {
+    false
+}
+
+fn retry_locked_with<T>(
+    delays: &[Duration],
+    mut op: impl FnMut() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let mut delays = delays.iter();
+    loop {
+        match op() {
+            Err(e) if is_locked(&e) => match delays.next() {
+                Some(delay) => std::thread::sleep(*delay),
+                None => return Err(e),
+            },
+            result => return result,
+        }
+    }
+}
+
+pub fn retry_locked<T>(op: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+    retry_locked_with(LOCK_RETRIES, op)
+}
+
+// fs_extra gives up on the first locked file, this waits for it
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    for entry in walkdir::WalkDir::new(from) {
+        let entry = entry?;
+        let target = to.join(entry.path().strip_prefix(from).unwrap());
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else {
+            retry_locked(|| std::fs::copy(entry.path(), &target))
+                .with_context(|| format!("Can't write {}", target.display()))?;
+        }
+    }
+    Ok(())
+}
+
 pub struct BasicTransaction {
     files: Box<dyn AsRef<Path>>,
 }
@@ -35,7 +93,7 @@ impl<'a, T: Transaction, B: AsRef<Path>> SafeTransaction<'a, T, B> {
         for path in self.transaction.relative_file_paths() {
             let root_path = root.join(&path);
             let backup_path = self.backup_dir.as_ref().join(&path);
-            match std::fs::File::open(&root_path) {
+            match retry_locked(|| std::fs::File::open(&root_path)) {
                 Err(e) if e.kind() == ErrorKind::NotFound => {}
                 Err(e) => return Err(e).with_context(|| root_path.display().to_string()),
                 Ok(mut f) => {
@@ -64,20 +122,14 @@ impl<'a, T: Transaction, B: AsRef<Path>> SafeTransaction<'a, T, B> {
 
     fn reverse(&self, root: &Path) -> Result<()> {
         for path in self.transaction.relative_file_paths() {
-            match std::fs::remove_file(root.join(path)) {
+            match retry_locked(|| std::fs::remove_file(root.join(&path))) {
                 Ok(()) => {}
                 Err(e) if e.kind() == ErrorKind::NotFound => {}
                 Err(e) => bail!("Can't remove new files: {}", e),
             };
         }
 
-        let mut opt = fs_extra::dir::CopyOptions::new();
-        opt.overwrite = true;
-        opt.content_only = true;
-        opt.copy_inside = true;
-        fs_extra::dir::copy(&self.backup_dir, root, &opt)?;
-
-        Ok(())
+        copy_dir(self.backup_dir.as_ref(), root)
     }
 }
 
@@ -123,13 +175,7 @@ pub trait Transaction {
 
 impl Transaction for BasicTransaction {
     fn run(&self, root_dir: &Path) -> Result<()> {
-        let mut opt = fs_extra::dir::CopyOptions::new();
-        opt.overwrite = true;
-        opt.content_only = true;
-        opt.copy_inside = true;
-        fs_extra::dir::copy(self.files.as_ref(), root_dir, &opt)?;
-
-        Ok(())
+        copy_dir(self.files.as_ref().as_ref(), root_dir)
     }
 
     fn relative_file_paths(&self) -> HashSet<PathBuf> {
@@ -233,11 +279,11 @@ impl<T: Transaction, B: AsRef<Path>> Drop for SafeTransaction<'_, T, B> {
 
 #[cfg(test)]
 mod tests {
-    use std::fs::File;
+    use std::{fs::File, io::ErrorKind, time::Duration};
 
     use tempfile::tempdir;
 
-    use crate::backup::{BasicTransaction, InDir, SafeTransaction, Transaction};
+    use crate::backup::{retry_locked_with, BasicTransaction, InDir, SafeTransaction, Transaction};
 
     #[test]
     fn relative_paths() {
@@ -344,4 +390,59 @@ mod tests {
 
         assert!(!backup_path.exists());
     }
+
+    #[cfg(windows)]
+    #[test]
+    fn locked_retry() {
+        let delays = [Duration::ZERO; 3];
+        let mut attempts = 0;
+        let result = retry_locked_with(&delays, || {
+            attempts += 1;
+            match attempts {
+                1 | 2 => Err(std::io::Error::from_raw_os_error(32)),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        attempts = 0;
+        let result: std::io::Result<()> = retry_locked_with(&delays, || {
+            attempts += 1;
+            Err(std::io::Error::from_raw_os_error(32))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 4);
+    }
+
+    #[test]
+    fn unlocked_no_retry() {
+        let delays = [Duration::ZERO; 3];
+        let mut attempts = 0;
+        let result: std::io::Result<()> = retry_locked_with(&delays, || {
+            attempts += 1;
+            Err(ErrorKind::NotFound.into())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        // a read-only file won't unlock by waiting
+        attempts = 0;
+        let result: std::io::Result<()> = retry_locked_with(&delays, || {
+            attempts += 1;
+            Err(std::io::Error::from_raw_os_error(5))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        #[cfg(not(windows))]
+        {
+            attempts = 0;
+            let result: std::io::Result<()> = retry_locked_with(&delays, || {
+                attempts += 1;
+                Err(std::io::Error::from_raw_os_error(32))
+            });
+            assert!(result.is_err());
+            assert_eq!(attempts, 1);
+        }
+    }
 }
@@ -1,17 +1,101 @@
 use std::path::Path;
 
+use anyhow::{Context, Result};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    addonlist::{Addons, FolderEntry, Modpack},
+    addonlist::{AddonKey, Addons, FolderEntry, Mo2Plugins, Modpack, OrderRules},
     app::AppContext,
+    mo2::Mo2Instance,
+    stats::AddonStats,
 };
 
+pub static BUNDLED_CONFIG: &str = include_str!("../resources/config.json");
+pub static USER_OVERRIDES: &str = "user_overrides.json";
+static INSTANCE_FILE: &str = "amt_instance.json";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ModpackConfig {
     metadata: Metadata,
     pub mods: IndexMap<String, FolderEntry>,
+    #[serde(default, skip_serializing_if = "Mo2Plugins::is_empty")]
+    pub mo2_plugins: Mo2Plugins,
+    #[serde(default, skip_serializing_if = "OrderRules::is_empty")]
+    pub order: OrderRules,
+}
+
+impl ModpackConfig {
+    pub fn load(config: &str, overrides: &Path) -> Result<Self> {
+        let config: Self = serde_json::from_str(config)?;
+        Ok(config.with_overrides(UserOverrides::load(overrides)?))
+    }
+
+    pub fn with_overrides(mut self, overrides: UserOverrides) -> Self {
+        for (addon, download) in overrides.downloads {
+            match self.mods.get_mut(&addon) {
+                Some(entry) => entry.download = download,
+                None => eprintln!(
+                    "Warning: override for {} skipped, it's not in the pack anymore",
+                    addon
+                ),
+            }
+        }
+
+        for addon in overrides.disabled {
+            if self.mods.shift_remove(&addon).is_none() {
+                eprintln!("Warning: can't disable {}, it's not in the pack anymore", addon);
+            }
+        }
+
+        // user addons go last, so they win conflicts with the pack
+        for (addon, entry) in overrides.extra_mods {
+            // insert alone would keep a replaced pack addon at its old position
+            self.mods.shift_remove(&addon);
+            self.mods.insert(addon, entry);
+        }
+        self.order.extend(overrides.order);
+
+        // an imported load order, addons it doesn't mention keep their place after it
+        if !overrides.load_order.is_empty() {
+            let position = |addon: &str| {
+                overrides
+                    .load_order
+                    .iter()
+                    .position(|a| a == addon)
+                    .unwrap_or(usize::MAX)
+            };
+            self.mods.sort_by(|a, _, b, _| position(a).cmp(&position(b)));
+        }
+        self
+    }
+}
+
+// Local changes to the pack, kept apart from the bundled config so they survive pack updates
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserOverrides {
+    pub disabled: Vec<String>,
+    pub downloads: IndexMap<String, AddonKey>,
+    pub extra_mods: IndexMap<String, FolderEntry>,
+    pub order: OrderRules,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub load_order: Vec<String>,
+}
+
+impl UserOverrides {
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(s) => serde_json::from_str(&s).with_context(|| path.display().to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -47,6 +131,11 @@ pub struct InstanceConfigData {
     current_profile: String,
     addons: Addons,
     profiles: Vec<Profile>,
+    #[serde(default)]
+    stats: IndexMap<String, AddonStats>,
+    // Files and torrent folders the tool put into MO2 downloads
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    downloads: Vec<String>,
 }
 
 impl InstanceConfigData {
@@ -59,21 +148,86 @@ impl InstanceConfigData {
             }],
             mo_dir: "mo2".to_owned(),
             current_profile: "Default".to_owned(),
+            stats: IndexMap::new(),
+            downloads: Vec::new(),
+        }
+    }
+
+    pub fn load(mo_dir: &Path) -> Result<Self> {
+        let path = mo_dir.join(INSTANCE_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s).with_context(|| path.display().to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self {
+                mo_dir: mo_dir.to_string_lossy().into_owned(),
+                ..Self::new()
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        std::fs::write(
+            self.mo_dir().join(INSTANCE_FILE),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn record_install(
+        &mut self,
+        addon: &str,
+        entry: &FolderEntry,
+        unpacked_size: u64,
+        archive_size: Option<u64>,
+    ) {
+        self.addons.insert(addon.to_owned(), entry.clone());
+        let stats = self.stats.entry(addon.to_owned()).or_default();
+        stats.unpacked_size = unpacked_size;
+        if archive_size.is_some() {
+            stats.archive_size = archive_size;
+        }
+    }
+
+    // What each addon was installed from, so the version that's actually on disk
+    pub fn installed(&self) -> impl Iterator<Item = (&str, &FolderEntry)> {
+        self.addons.iter().map(|(name, entry)| (name.as_str(), entry))
+    }
+
+    pub fn stats(&self) -> &IndexMap<String, AddonStats> {
+        &self.stats
+    }
+
+    pub fn record_download(&mut self, file_name: &str) {
+        if !self.downloads.iter().any(|d| d == file_name) {
+            self.downloads.push(file_name.to_owned());
         }
     }
 
+    pub fn downloads(&self) -> &[String] {
+        &self.downloads
+    }
+
+    pub fn forget_downloads(&mut self) {
+        self.downloads.clear();
+    }
+
     pub fn mo_dir(&self) -> &Path {
         Path::new(&self.mo_dir)
     }
 
-    pub fn missing_addons(&self) -> Vec<&str> {
-        let mods_dir = self.mo_dir().join("mods");
-        self.addons
+    pub fn mo2(&self) -> Result<Mo2Instance> {
+        Mo2Instance::open(self.mo_dir())
+    }
+
+    pub fn missing_addons(&self) -> Result<Vec<&str>> {
+        let mods_dir = self.mo2()?.mods_dir;
+        Ok(self
+            .addons
             .iter()
             .map(|(k, _)| k)
             .filter(|m| !mods_dir.join(m).is_dir())
             .map(|m| m.as_str())
-            .collect()
+            .collect())
     }
 
     pub fn missing_modpack_addons<'a>(&self, modpack: &'a Modpack) -> Vec<&'a str> {
@@ -86,16 +240,8 @@ impl InstanceConfigData {
         missing
     }
 
-    pub fn unknown_addons(&self) -> Vec<String> {
-        let mut unknown = Vec::new();
-        for dir in std::fs::read_dir(self.mo_dir().join("mods")).unwrap() {
-            let name = dir.unwrap().file_name();
-            let s = name.to_str().unwrap();
-            if self.addons.get(s).is_none() {
-                unknown.push(s.to_owned());
-            }
-        }
-        unknown
+    pub fn unknown_addons(&self) -> Result<Vec<String>> {
+        self.addons.unknown_addons(&self.mo2()?.mods_dir)
     }
 }
 
@@ -104,11 +250,12 @@ mod tests {
     use tempfile::tempdir;
 
     use crate::{
-        addonlist::{AddonKey, Addons, FolderEntry, UrlLink},
+        addonlist::{AddonKey, Addons, FolderEntry},
         config::ModpackConfig,
+        net::UrlLink,
     };
 
-    use super::{InstanceConfigData, Profile};
+    use super::{InstanceConfigData, Profile, UserOverrides};
 
     static TEST_CONFIG: &str = include_str!("../resources/config.json");
 
@@ -121,6 +268,94 @@ mod tests {
         );
     }
 
+    #[test]
+    fn mo2_plugins() {
+        let mut config: serde_json::Value = serde_json::from_str(TEST_CONFIG).unwrap();
+        config["mo2_plugins"] = serde_json::json!({
+            "rootbuilder": {
+                "download": {
+                    "type": "url",
+                    "url": "https://example.com/RootBuilder.zip"
+                },
+                "addon_folder": "rootbuilder"
+            }
+        });
+
+        let config: ModpackConfig = serde_json::from_value(config).unwrap();
+        assert!(!config.mo2_plugins.is_empty());
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["mo2_plugins"]["rootbuilder"]["addon_folder"], "rootbuilder");
+    }
+
+    #[test]
+    fn user_overrides() {
+        let config: ModpackConfig = serde_json::from_str(TEST_CONFIG).unwrap();
+        let overrides: UserOverrides = serde_json::from_str(
+            r#"{
+                "disabled": ["Arszi_Task_Pack", "Removed_From_Pack"],
+                "downloads": {
+                    "anomaly-speed": { "type": "url", "url": "https://example.com/speed.zip" }
+                },
+                "extra_mods": {
+                    "Weird_Tasks_Framework": { "download": { "type": "url", "url": "https://example.com/wtf.zip" } },
+                    "Igigui": { "download": { "type": "url", "url": "https://example.com/igigui.zip" } }
+                },
+                "order": {
+                    "after": [{ "addon": "anomaly-speed", "after": "Igigui" }]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let config = config.with_overrides(overrides);
+        assert!(!config.mods.contains_key("Arszi_Task_Pack"));
+        assert_eq!(
+            config.mods["anomaly-speed"].download,
+            AddonKey::Url(UrlLink::new("https://example.com/speed.zip".to_owned()))
+        );
+        assert_eq!(config.mods.last().unwrap().0, "Igigui");
+        assert_eq!(config.mods.get_index(4).unwrap().0, "Weird_Tasks_Framework");
+        assert_eq!(config.mods.len(), 6);
+        assert_eq!(config.order.after.len(), 1);
+    }
+
+    #[test]
+    fn imported_load_order() {
+        let config: ModpackConfig = serde_json::from_str(TEST_CONFIG).unwrap();
+        let first = config.mods.keys().next().unwrap().clone();
+        let last = config.mods.keys().last().unwrap().clone();
+        let overrides = UserOverrides {
+            load_order: vec![last.clone(), "Not_In_Pack".to_owned(), first.clone()],
+            ..Default::default()
+        };
+
+        let config = config.with_overrides(overrides);
+        let order: Vec<&String> = config.mods.keys().collect();
+        assert_eq!(order[0], &last);
+        assert_eq!(order[1], &first);
+        assert_eq!(order.len(), 6);
+    }
+
+    #[test]
+    fn install_stats() {
+        let tmp = tempdir().unwrap();
+        let entry = FolderEntry::new(AddonKey::Url(UrlLink::new("".to_owned())), None);
+
+        let mut instance = InstanceConfigData::load(tmp.path()).unwrap();
+        instance.record_install("Igigui", &entry, 4096, Some(1024));
+        // resumed from staging, the archive is gone but the old size still holds
+        instance.record_install("Igigui", &entry, 5000, None);
+        instance.record_download("igigui.zip");
+        instance.record_download("igigui.zip");
+        instance.save().unwrap();
+
+        let instance = InstanceConfigData::load(tmp.path()).unwrap();
+        assert_eq!(instance.stats()["Igigui"].unpacked_size, 5000);
+        assert_eq!(instance.stats()["Igigui"].archive_size, Some(1024));
+        assert!(instance.addons.get("Igigui").is_some());
+        assert_eq!(instance.downloads(), ["igigui.zip"]);
+    }
+
     #[test]
     fn unknown_addons() {
         let tmp = tempdir().unwrap();
@@ -140,15 +375,22 @@ mod tests {
             current_profile: "Default".to_owned(),
             addons,
             profiles: vec![Profile::default()],
+            stats: Default::default(),
+            downloads: Vec::new(),
         };
 
         let expected = vec!["abb", "hehe"];
-        let missing = config.unknown_addons();
+        let missing = config.unknown_addons().unwrap();
 
         assert_eq!(missing.len(), expected.len());
         for s in expected {
             assert!(missing.contains(&s.to_owned()));
         }
+
+        // an unreadable ModOrganizer.ini is an error, not a panic
+        std::fs::create_dir(tmp.path().join("ModOrganizer.ini")).unwrap();
+        assert!(config.unknown_addons().is_err());
+        assert!(config.missing_addons().is_err());
     }
     #[test]
     fn missing_addons() {
@@ -169,10 +411,12 @@ mod tests {
             current_profile: "Default".to_owned(),
             addons,
             profiles: vec![Profile::default()],
+            stats: Default::default(),
+            downloads: Vec::new(),
         };
 
         let expected = vec!["abb", "hehe"];
-        let missing = config.missing_addons();
+        let missing = config.missing_addons().unwrap();
 
         assert_eq!(missing.len(), expected.len());
         for s in expected {
@@ -0,0 +1,160 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+// Fixes applied to an unpacked archive before its addon folder is looked up.
+// Paths are relative to the archive root and use '/' as separator.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Hook {
+    // move the content of a folder one level up and drop the folder
+    Flatten { folder: String },
+    Rename { from: String, to: String },
+    Delete { paths: Vec<String> },
+}
+
+fn resolve(root: &Path, path: &str) -> Result<PathBuf> {
+    let relative = Path::new(path);
+    let inside = relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    // "" and "." are the archive root itself, flattening it would spill into the parent
+    let below_root = relative
+        .components()
+        .any(|c| matches!(c, Component::Normal(_)));
+    if !inside || !below_root {
+        bail!("Hook path must point inside the archive: {:?}", path);
+    }
+    Ok(root.join(relative))
+}
+
+impl Hook {
+    pub fn apply(&self, root: &Path) -> Result<()> {
+        match self {
+            Hook::Flatten { folder } => flatten(&resolve(root, folder)?),
+            Hook::Rename { from, to } => {
+                let (from, to) = (resolve(root, from)?, resolve(root, to)?);
+                if to.exists() {
+                    bail!("Can't rename {}, {} already exists", from.display(), to.display());
+                }
+                if let Some(parent) = to.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(&from, &to).with_context(|| from.display().to_string())
+            }
+            Hook::Delete { paths } => {
+                for path in paths {
+                    let path = resolve(root, path)?;
+                    if path.is_dir() {
+                        std::fs::remove_dir_all(&path)?;
+                    } else if path.exists() {
+                        std::fs::remove_file(&path)?;
+                    } else {
+                        println!("Nothing to delete at {}", path.display());
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn apply_all(hooks: &[Hook], root: &Path) -> Result<()> {
+        hooks.iter().try_for_each(|h| h.apply(root))
+    }
+}
+
+fn flatten(dir: &Path) -> Result<()> {
+    let parent = dir.parent().unwrap();
+    if !dir.is_dir() {
+        bail!("Can't flatten {}, not a folder", dir.display());
+    }
+
+    // move it aside first, it may contain an entry with its own name (gamedata/gamedata)
+    let moved = parent.join(format!(
+        "{}.amt_flatten",
+        dir.file_name().unwrap().to_string_lossy()
+    ));
+    std::fs::rename(dir, &moved)?;
+
+    for entry in std::fs::read_dir(&moved)? {
+        let entry = entry?;
+        let target = parent.join(entry.file_name());
+        if target.exists() {
+            bail!("Can't flatten {}, {} already exists", dir.display(), target.display());
+        }
+        std::fs::rename(entry.path(), target)?;
+    }
+    std::fs::remove_dir(&moved)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use tempfile::tempdir;
+
+    use super::Hook;
+
+    fn touch(path: &Path) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::File::create(path).unwrap();
+    }
+
+    #[test]
+    fn hooks() {
+        let root = tempdir().unwrap();
+        let root = root.path();
+        touch(&root.join("Main Files/gamedata/gamedata/scripts/a.script"));
+        touch(&root.join("Main Files/readme.txt"));
+        touch(&root.join("Main Files/Thumbs.db"));
+
+        let hooks: Vec<Hook> = serde_json::from_str(
+            r#"[
+                {"type": "flatten", "folder": "Main Files/gamedata"},
+                {"type": "delete", "paths": ["Main Files/readme.txt", "Main Files/Thumbs.db"]},
+                {"type": "rename", "from": "Main Files", "to": "My_Addon"}
+            ]"#,
+        )
+        .unwrap();
+        Hook::apply_all(&hooks, root).unwrap();
+
+        assert!(root.join("My_Addon/gamedata/scripts/a.script").is_file());
+        assert!(!root.join("My_Addon/readme.txt").exists());
+        assert!(!root.join("My_Addon/Thumbs.db").exists());
+        assert!(!root.join("Main Files").exists());
+    }
+
+    #[test]
+    fn outside_paths() {
+        let root = tempdir().unwrap();
+        let hook = Hook::Delete {
+            paths: vec!["../important".to_owned()],
+        };
+        assert!(hook.apply(root.path()).is_err());
+
+        let hook = Hook::Flatten {
+            folder: "missing".to_owned(),
+        };
+        assert!(hook.apply(root.path()).is_err());
+
+        std::fs::create_dir(root.path().join("gamedata")).unwrap();
+        for path in ["", ".", "./.", "/", "gamedata/.."] {
+            let hook = Hook::Flatten {
+                folder: path.to_owned(),
+            };
+            assert!(hook.apply(root.path()).is_err(), "{:?}", path);
+            let hook = Hook::Rename {
+                from: path.to_owned(),
+                to: "renamed".to_owned(),
+            };
+            assert!(hook.apply(root.path()).is_err(), "{:?}", path);
+            let hook = Hook::Delete {
+                paths: vec![path.to_owned()],
+            };
+            assert!(hook.apply(root.path()).is_err(), "{:?}", path);
+        }
+        assert!(root.path().join("gamedata").is_dir());
+    }
+}
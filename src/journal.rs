@@ -0,0 +1,225 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+
+use crate::addonlist::AddonKey;
+
+static JOURNAL_FILE: &str = "amt_install.json";
+pub static STAGING_DIR: &str = "amt_staging";
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum AddonState {
+    Installing,
+    Installed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StagedDownload {
+    download: AddonKey,
+    dir: String,
+    // file name of the archive kept in `<dir>_archive`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    archive: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Staged {
+    pub dir: PathBuf,
+    // kept so a resumed install can still put it in MO2 downloads
+    pub archive: Option<PathBuf>,
+}
+
+// Survives the process being killed: every state change is written to disk
+// right away, so the next install knows what was finished and what was not.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct InstallJournal {
+    #[serde(skip)]
+    root: PathBuf,
+    addons: IndexMap<String, AddonState>,
+    staged: Vec<StagedDownload>,
+}
+
+impl InstallJournal {
+    pub fn exists(root: &Path) -> bool {
+        root.join(JOURNAL_FILE).is_file()
+    }
+
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(JOURNAL_FILE);
+        let mut journal: Self = match std::fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s)
+                .with_context(|| format!("Broken install journal: {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e.into()),
+        };
+        journal.root = root.to_owned();
+        Ok(journal)
+    }
+
+    fn save(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.root)?;
+        // write-then-rename, a journal cut in half is worse than no journal
+        let tmp = self.root.join(format!("{}.tmp", JOURNAL_FILE));
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp, self.root.join(JOURNAL_FILE))?;
+        Ok(())
+    }
+
+    pub fn state(&self, addon: &str) -> Option<AddonState> {
+        self.addons.get(addon).copied()
+    }
+
+    pub fn set_state(&mut self, addon: &str, state: AddonState) -> Result<()> {
+        self.addons.insert(addon.to_owned(), state);
+        self.save()
+    }
+
+    pub fn interrupted(&self) -> Vec<&str> {
+        self.addons
+            .iter()
+            .filter(|(_, s)| **s == AddonState::Installing)
+            .map(|(a, _)| a.as_str())
+            .collect()
+    }
+
+    pub fn staged_count(&self) -> usize {
+        self.staged.len()
+    }
+
+    pub fn staged(&self, key: &AddonKey) -> Option<Staged> {
+        let staged = self.staged.iter().find(|s| &s.download == key)?;
+        let staging = self.root.join(STAGING_DIR);
+        let dir = staging.join(&staged.dir);
+        if !dir.is_dir() {
+            return None;
+        }
+        let archive = staged
+            .archive
+            .as_ref()
+            .map(|a| staging.join(format!("{}_archive", staged.dir)).join(a))
+            .filter(|a| a.is_file());
+        Some(Staged { dir, archive })
+    }
+
+    pub fn stage(
+        &mut self,
+        key: &AddonKey,
+        unpacked: TempDir,
+        archive: Option<(&Path, &str)>,
+    ) -> Result<Staged> {
+        self.staged.retain(|s| &s.download != key);
+        let dir = (0..)
+            .map(|i| i.to_string())
+            .find(|d| !self.staged.iter().any(|s| &s.dir == d))
+            .unwrap();
+        let staging = self.root.join(STAGING_DIR);
+        let path = staging.join(&dir);
+        let archive_dir = staging.join(format!("{}_archive", dir));
+        for old in [&path, &archive_dir] {
+            if old.exists() {
+                std::fs::remove_dir_all(old)?;
+            }
+        }
+        std::fs::create_dir_all(&path)?;
+
+        let mut opt = fs_extra::dir::CopyOptions::new();
+        opt.content_only = true;
+        fs_extra::dir::move_dir(unpacked.path(), &path, &opt)?;
+
+        // copied, the temp file is usually on another drive
+        let archive_path = match archive {
+            Some((file, name)) => {
+                std::fs::create_dir_all(&archive_dir)?;
+                let archive_path = archive_dir.join(name);
+                std::fs::copy(file, &archive_path)?;
+                Some(archive_path)
+            }
+            None => None,
+        };
+
+        self.staged.push(StagedDownload {
+            download: key.clone(),
+            dir,
+            archive: archive.map(|(_, name)| name.to_owned()),
+        });
+        self.save()?;
+        Ok(Staged {
+            dir: path,
+            archive: archive_path,
+        })
+    }
+
+    pub fn finish(self) -> Result<()> {
+        let staging = self.root.join(STAGING_DIR);
+        if staging.exists() {
+            std::fs::remove_dir_all(staging)?;
+        }
+        // nothing was pending, so the journal was never written
+        match std::fs::remove_file(self.root.join(JOURNAL_FILE)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::{addonlist::AddonKey, net::UrlLink};
+
+    use super::{AddonState, InstallJournal};
+
+    #[test]
+    fn resume_state() {
+        let tmp = tempdir().unwrap();
+        let mut journal = InstallJournal::load(tmp.path()).unwrap();
+        journal.set_state("Igigui", AddonState::Installed).unwrap();
+        journal
+            .set_state("Weird_Tasks_Framework", AddonState::Installing)
+            .unwrap();
+        drop(journal);
+
+        assert!(InstallJournal::exists(tmp.path()));
+        let journal = InstallJournal::load(tmp.path()).unwrap();
+        assert_eq!(journal.state("Igigui"), Some(AddonState::Installed));
+        assert_eq!(journal.state("Arszi_Task_Pack"), None);
+        assert_eq!(journal.interrupted(), vec!["Weird_Tasks_Framework"]);
+    }
+
+    #[test]
+    fn staging() {
+        let tmp = tempdir().unwrap();
+        let key = AddonKey::Url(UrlLink::new("https://example.com/a.zip".to_owned()));
+        let other = AddonKey::Url(UrlLink::new("https://example.com/b.zip".to_owned()));
+
+        let unpacked = tempdir().unwrap();
+        std::fs::create_dir_all(unpacked.path().join("gamedata/scripts")).unwrap();
+        std::fs::File::create(unpacked.path().join("gamedata/scripts/a.script")).unwrap();
+
+        let archive = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(archive.path(), "zip").unwrap();
+
+        let mut journal = InstallJournal::load(tmp.path()).unwrap();
+        let staged = journal
+            .stage(&key, unpacked, Some((archive.path(), "a.zip")))
+            .unwrap();
+        assert!(staged.dir.join("gamedata/scripts/a.script").is_file());
+        assert_eq!(std::fs::read(staged.archive.as_ref().unwrap()).unwrap(), b"zip");
+
+        let journal = InstallJournal::load(tmp.path()).unwrap();
+        assert_eq!(journal.staged(&key), Some(staged));
+        assert_eq!(journal.staged(&other), None);
+
+        journal.finish().unwrap();
+        assert!(!InstallJournal::exists(tmp.path()));
+        assert!(!tmp.path().join("amt_staging").exists());
+
+        // an already complete install has nothing to clean up
+        InstallJournal::load(tmp.path()).unwrap().finish().unwrap();
+    }
+}
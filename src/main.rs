@@ -4,33 +4,139 @@ mod app;
 mod config;
 mod backup;
 mod addonlist;
+mod hooks;
+mod journal;
+mod mo2;
+mod net;
+mod processes;
+mod settings;
+mod stats;
+mod status;
+mod updates;
 
 use std::{io::Read, path::Path};
-use anyhow::Result;
+use anyhow::{Context, Result};
 
-use addonlist::Modpack;
+use addonlist::{InstallOptions, LoadOrder, Modpack};
 use app::TemplateApp;
-use config::ModpackConfig;
-
-use crate::actions::{download_7zip, download_file, unpack_temporary};
-
-/* fn main() {
-    eframe::run_native(
-        "Anomaly modding tool",
-        eframe::NativeOptions::default(),
-        Box::new(|cc| Box::new(TemplateApp::new(cc))),
-    );
-} */
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    let config_str = include_str!("../resources/config.json");
-    let config: ModpackConfig = serde_json::from_str(config_str).unwrap();
-    let pack: Modpack = config.into();
+use config::{ModpackConfig, BUNDLED_CONFIG, USER_OVERRIDES};
+use journal::InstallJournal;
+use mo2::Mo2Instance;
+use settings::SETTINGS;
+use status::HealthReport;
+
+use crate::actions::{download_7zip, unpack_temporary};
+
+// --name value or --name=value
+fn flag_value(name: &str) -> Option<String> {
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return args.next();
+        }
+        if let Some(value) = arg.strip_prefix(name).and_then(|a| a.strip_prefix('=')) {
+            return Some(value.to_owned());
+        }
+    }
+    None
+}
+
+fn kib_flag(name: &str) -> Result<Option<u32>> {
+    flag_value(name)
+        .map(|v| v.parse().with_context(|| format!("{} expects KiB/s, got {}", name, v)))
+        .transpose()
+}
+
+fn main() -> Result<()> {
+    if std::env::args().nth(1).as_deref() == Some("gui") {
+        // the app blocks on runtimes of its own, it can't live inside the CLI one
+        eframe::run_native(
+            "Anomaly modding tool",
+            eframe::NativeOptions::default(),
+            Box::new(|cc| Box::new(TemplateApp::new(cc))),
+        );
+        return Ok(());
+    }
+    tokio::runtime::Runtime::new()?.block_on(cli())
+}
+
+async fn cli() -> Result<()> {
+    {
+        let mut settings = SETTINGS.write();
+        if let Some(limit) = kib_flag("--limit-rate")? {
+            settings.download_limit = Some(limit);
+        }
+        if let Some(limit) = kib_flag("--limit-connection")? {
+            settings.connection_limit = Some(limit);
+        }
+    }
+
+    let config = ModpackConfig::load(BUNDLED_CONFIG, Path::new(USER_OVERRIDES))?;
+    let pack = Modpack::try_from(config)?;
+    let anomaly_dir = std::env::current_dir()?;
+    let mo2 = match Mo2Instance::detect(&anomaly_dir)? {
+        Some(mo2) => mo2,
+        None => Mo2Instance::open(Path::new("mo2"))?,
+    };
+
+    match std::env::args().nth(1).as_deref() {
+        Some("status") => {
+            let report = HealthReport::collect(&anomaly_dir, &pack)?;
+            print!("{}", report);
+            std::process::exit(if report.is_healthy() { 0 } else { 1 });
+        }
+        // export-order [--modlist] [--out file]
+        Some("export-order") => {
+            let text = if std::env::args().any(|a| a == "--modlist") {
+                pack.order().to_modorg_modlist()
+            } else {
+                pack.order().to_plain_text()
+            };
+            match flag_value("--out") {
+                Some(path) => std::fs::write(path, text)?,
+                None => print!("{}", text),
+            }
+            return Ok(());
+        }
+        // import-order <modlist.txt or plain list>
+        Some("import-order") => {
+            let path = std::env::args()
+                .nth(2)
+                .context("import-order expects a file with the load order")?;
+            let text = std::fs::read_to_string(&path).with_context(|| path.clone())?;
+            LoadOrder::import(
+                &text,
+                Some(&mo2),
+                Path::new(USER_OVERRIDES),
+                processes::console_prompt,
+            )?;
+            println!("Load order imported from {}", path);
+            return Ok(());
+        }
+        _ => {}
+    }
+
     let unpacker = download_7zip().await?;
-    let mo_dir = Path::new("mo2");
-    pack.install(mo_dir, &unpacker).await?;
-    pack.enable(mo_dir).unwrap();
+    if InstallJournal::exists(&mo2.base_dir) {
+        println!("Found an unfinished install, resuming");
+    }
+    let options = InstallOptions {
+        keep_archives: std::env::args().any(|a| a == "--keep-archives"),
+        no_seeding: std::env::args().any(|a| a == "--no-seed"),
+    };
+    processes::wait_until_closed(processes::console_prompt)?;
+    pack.install(&mo2, &unpacker, &options).await?;
+    pack.enable(&mo2).unwrap();
+
+    // the session only seeds while we're running
+    let seeding = net::seeding_torrents();
+    if seeding > 0 {
+        println!(
+            "Seeding {} torrent(s), press Enter to stop. Pass --no-seed to skip this",
+            seeding
+        );
+        tokio::task::spawn_blocking(|| std::io::stdin().read_line(&mut String::new())).await??;
+    }
 
     Ok(())
 }
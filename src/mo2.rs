@@ -0,0 +1,273 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{addonlist::AddonKey, net::sanitize_file_name};
+
+pub static MO2_INI: &str = "ModOrganizer.ini";
+
+type Ini = HashMap<String, HashMap<String, String>>;
+
+fn parse_ini(content: &str) -> Ini {
+    let mut ini = Ini::new();
+    let mut section = String::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.to_owned();
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            ini.entry(section.clone())
+                .or_default()
+                .insert(key.trim().to_owned(), unquote(value.trim()));
+        }
+    }
+    ini
+}
+
+// Qt writes paths as @ByteArray(D:\\Games\\Anomaly) or "D:/Games/Anomaly"
+fn unquote(value: &str) -> String {
+    let value = value
+        .strip_prefix("@ByteArray(")
+        .and_then(|v| v.strip_suffix(')'))
+        .unwrap_or(value);
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    value.replace(r"\\", r"\")
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    let normalize = |p: &Path| {
+        p.to_string_lossy()
+            .replace('\\', "/")
+            .trim_end_matches('/')
+            .to_lowercase()
+    };
+    normalize(a) == normalize(b)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mo2Instance {
+    pub base_dir: PathBuf,
+    pub mods_dir: PathBuf,
+    pub profiles_dir: PathBuf,
+    pub downloads_dir: PathBuf,
+    pub version: Option<String>,
+}
+
+impl Mo2Instance {
+    pub fn open(instance_dir: &Path) -> Result<Self> {
+        let ini_path = instance_dir.join(MO2_INI);
+        let ini = match std::fs::read_to_string(&ini_path) {
+            Ok(s) => parse_ini(&s),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ini::new(),
+            Err(e) => return Err(e).with_context(|| ini_path.display().to_string()),
+        };
+        Ok(Self::from_ini(instance_dir, &ini))
+    }
+
+    fn from_ini(instance_dir: &Path, ini: &Ini) -> Self {
+        let settings = ini.get("Settings");
+        let setting = |key: &str| settings.and_then(|s| s.get(key)).filter(|v| !v.is_empty());
+
+        let base_dir = setting("base_directory")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| instance_dir.to_owned());
+        let dir = |key: &str, default: &str| {
+            setting(key)
+                .map(|v| PathBuf::from(v.replace("%BASE_DIR%", &base_dir.to_string_lossy())))
+                .unwrap_or_else(|| base_dir.join(default))
+        };
+
+        Self {
+            mods_dir: dir("mod_directory", "mods"),
+            profiles_dir: dir("profiles_directory", "profiles"),
+            downloads_dir: dir("download_directory", "downloads"),
+            version: ini.get("General").and_then(|g| g.get("version")).cloned(),
+            base_dir,
+        }
+    }
+
+    pub fn detect(anomaly_dir: &Path) -> Result<Option<Self>> {
+        let global_root = std::env::var_os("LOCALAPPDATA")
+            .map(|d| PathBuf::from(d).join("ModOrganizer"));
+        Self::detect_in(anomaly_dir, global_root.as_deref())
+    }
+
+    fn detect_in(anomaly_dir: &Path, global_root: Option<&Path>) -> Result<Option<Self>> {
+        let portable = anomaly_dir.join("mo2");
+        if portable.join("portable.txt").is_file() || portable.join(MO2_INI).is_file() {
+            return Self::open(&portable).map(Some);
+        }
+
+        let instances = match global_root.filter(|d| d.is_dir()) {
+            Some(root) => std::fs::read_dir(root)?.collect::<std::io::Result<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        for dir in instances {
+            let instance_dir = dir.path();
+            let Ok(content) = std::fs::read_to_string(instance_dir.join(MO2_INI)) else {
+                continue;
+            };
+            let ini = parse_ini(&content);
+            let game_path = ini.get("General").and_then(|g| g.get("gamePath"));
+            if game_path.is_some_and(|p| same_path(Path::new(p), anomaly_dir)) {
+                return Ok(Some(Self::from_ini(&instance_dir, &ini)));
+            }
+        }
+
+        if portable.is_dir() {
+            return Self::open(&portable).map(Some);
+        }
+        Ok(None)
+    }
+}
+
+// MO2 only lists archives in its downloads tab if they have a .meta next to them
+fn download_meta(file_name: &str, key: &AddonKey) -> String {
+    [
+        "[General]".to_owned(),
+        "gameName=stalkeranomaly".to_owned(),
+        "modID=0".to_owned(),
+        "fileID=0".to_owned(),
+        format!("name={}", file_name),
+        format!("url={}", key.source_url()),
+        format!("version={}", key.version().unwrap_or_default()),
+        "installed=true".to_owned(),
+        "uninstalled=false".to_owned(),
+        "paused=false".to_owned(),
+        "removed=false".to_owned(),
+    ]
+    .join("\n")
+        + "\n"
+}
+
+pub fn place_download(
+    downloads_dir: &Path,
+    archive: &Path,
+    file_name: &str,
+    key: &AddonKey,
+) -> Result<String> {
+    let file_name = sanitize_file_name(file_name)
+        .with_context(|| format!("Not a file name: {}", file_name))?;
+    std::fs::create_dir_all(downloads_dir)?;
+    std::fs::copy(archive, downloads_dir.join(&file_name))?;
+    write_download_meta(downloads_dir, &file_name, key)?;
+    Ok(file_name)
+}
+
+pub fn write_download_meta(downloads_dir: &Path, file_name: &str, key: &AddonKey) -> Result<()> {
+    std::fs::write(
+        downloads_dir.join(format!("{}.meta", file_name)),
+        download_meta(file_name, key),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use tempfile::{tempdir, NamedTempFile};
+
+    use crate::{addonlist::AddonKey, net::ModdbLink};
+
+    use super::{place_download, Mo2Instance};
+
+    #[test]
+    fn portable_instance() {
+        let anomaly = tempdir().unwrap();
+        let mo_dir = anomaly.path().join("mo2");
+        std::fs::create_dir(&mo_dir).unwrap();
+        std::fs::write(mo_dir.join("portable.txt"), "").unwrap();
+
+        let instance = Mo2Instance::detect_in(anomaly.path(), None).unwrap().unwrap();
+        assert_eq!(instance.mods_dir, mo_dir.join("mods"));
+        assert_eq!(instance.profiles_dir, mo_dir.join("profiles"));
+        assert_eq!(instance.downloads_dir, mo_dir.join("downloads"));
+    }
+
+    #[test]
+    fn global_instance() {
+        let anomaly = tempdir().unwrap();
+        let global = tempdir().unwrap();
+        let other = global.path().join("Skyrim");
+        let instance_dir = global.path().join("Anomaly");
+        std::fs::create_dir(&other).unwrap();
+        std::fs::create_dir(&instance_dir).unwrap();
+        std::fs::write(
+            other.join("ModOrganizer.ini"),
+            "[General]\ngamePath=@ByteArray(C:\\\\Games\\\\Skyrim)\n",
+        )
+        .unwrap();
+        std::fs::write(
+            instance_dir.join("ModOrganizer.ini"),
+            format!(
+                "[General]\ngamePath=@ByteArray({})\nversion=2.4.4\n\n[Settings]\nmod_directory=E:/mo2mods\ndownload_directory=%BASE_DIR%/dl\n",
+                anomaly.path().display()
+            ),
+        )
+        .unwrap();
+
+        let instance = Mo2Instance::detect_in(anomaly.path(), Some(global.path()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(instance.base_dir, instance_dir);
+        assert_eq!(instance.version.as_deref(), Some("2.4.4"));
+        assert_eq!(instance.mods_dir, Path::new("E:/mo2mods"));
+        assert_eq!(instance.profiles_dir, instance_dir.join("profiles"));
+        assert_eq!(
+            instance.downloads_dir,
+            Path::new(&format!("{}/dl", instance_dir.display()))
+        );
+    }
+
+    #[test]
+    fn no_instance() {
+        let anomaly = tempdir().unwrap();
+        let global = tempdir().unwrap();
+        assert_eq!(
+            Mo2Instance::detect_in(anomaly.path(), Some(global.path())).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn download_meta() {
+        let downloads = tempdir().unwrap();
+        let archive = NamedTempFile::new().unwrap();
+        std::fs::write(archive.path(), b"not really a zip").unwrap();
+        let key = AddonKey::Moddb(ModdbLink {
+            addon_link: "anomaly-mod-configuration-menu".to_owned(),
+            updated: "Aug 8th, 2022".to_owned(),
+        });
+
+        place_download(downloads.path(), archive.path(), "mcm.zip", &key).unwrap();
+
+        assert_eq!(
+            std::fs::read(downloads.path().join("mcm.zip")).unwrap(),
+            b"not really a zip"
+        );
+        let meta = std::fs::read_to_string(downloads.path().join("mcm.zip.meta")).unwrap();
+        assert!(meta.starts_with("[General]\n"));
+        assert!(meta.contains(
+            "url=https://www.moddb.com/mods/stalker-anomaly/addons/anomaly-mod-configuration-menu\n"
+        ));
+        assert!(meta.contains("version=Aug 8th, 2022\n"));
+        assert!(meta.contains("installed=true\n"));
+
+        let placed =
+            place_download(downloads.path(), archive.path(), "../../evil.zip\"", &key).unwrap();
+        assert_eq!(placed, "evil.zip");
+        assert!(downloads.path().join("evil.zip").is_file());
+        assert!(place_download(downloads.path(), archive.path(), "..", &key).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use std::borrow::Cow;
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{client, LinkResolver};
+
+#[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Clone, Eq)]
+pub struct GithubLink {
+    pub repo: String,
+    pub tag: String,
+    pub filename: String,
+}
+
+impl GithubLink {
+    async fn fetch_tag(&self) -> Result<Cow<'_, str>> {
+        if self.tag != "latest" {
+            return Ok(Cow::Borrowed(&self.tag));
+        }
+
+        let resp = client()?
+            .head(format!(
+                r"https://github.com/{repo}/releases/latest",
+                repo = self.repo
+            ))
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            bail!("No such repo");
+        }
+
+        let last_segment = resp
+            .url()
+            .path_segments()
+            .and_then(|mut s| s.next_back())
+            .ok_or_else(|| anyhow!("Unexpected release url {}", resp.url()))?;
+        let tag = match last_segment {
+            "releases" => bail!("No releases in repo"),
+            x => x,
+        };
+        Ok(Cow::Owned(tag.to_owned()))
+    }
+
+    pub async fn latest_tag(&self) -> Result<String> {
+        let latest = GithubLink {
+            tag: "latest".to_owned(),
+            ..self.clone()
+        };
+        Ok(latest.fetch_tag().await?.into_owned())
+    }
+}
+
+impl LinkResolver for GithubLink {
+    async fn download_url(&self) -> Result<String> {
+        let tag = self.fetch_tag().await?;
+        let version = match tag.strip_prefix('v') {
+            // if tag starts with v (v3.2 for example), strips v
+            Some(v) => v,
+            None => &tag,
+        };
+        let filename = self.filename.replace("$VERSION", version);
+        Ok(format!(
+            "https://github.com/{repo}/releases/download/{tag}/{filename}",
+            repo = self.repo
+        ))
+    }
+
+    fn page_url(&self) -> String {
+        format!("https://github.com/{}", self.repo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::{client, LinkResolver};
+
+    use super::GithubLink;
+
+    #[tokio::test]
+    async fn github_download_link_tagged() {
+        let key = GithubLink {
+            repo: "ModOrganizer2/modorganizer".to_owned(),
+            tag: "v2.4.3".to_owned(),
+            filename: "Mod.Organizer-$VERSION.7z".to_owned(),
+        };
+
+        let expected = "https://github.com/ModOrganizer2/modorganizer/releases/download/v2.4.3/Mod.Organizer-2.4.3.7z";
+        assert_eq!(key.download_url().await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn github_download_link_latest() {
+        let key = GithubLink {
+            repo: "ModOrganizer2/modorganizer".to_owned(),
+            tag: "latest".to_owned(),
+            filename: "Mod.Organizer-$VERSION.7z".to_owned(),
+        };
+
+        let not_expected = "https://github.com/ModOrganizer2/modorganizer/releases/download/latest/Mod.Organizer-latest.7z";
+        let url = key.download_url().await.unwrap();
+        assert_ne!(url, not_expected);
+        assert!(client()
+            .unwrap()
+            .head(url)
+            .send()
+            .await
+            .unwrap()
+            .content_length()
+            .is_some());
+    }
+}
@@ -0,0 +1,323 @@
+use std::io::BufWriter;
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::stream::StreamExt;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::IntoUrl;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+use crate::settings::SETTINGS;
+
+mod github;
+mod moddb;
+mod throttle;
+mod torrent;
+
+pub use github::GithubLink;
+pub use moddb::ModdbLink;
+pub use torrent::{seeding_torrents, TorrentLink};
+
+use throttle::{throttle, Throttle, GLOBAL_THROTTLE};
+
+// Built once, so network settings are picked up on the next start
+static CLIENT: Lazy<Result<reqwest::Client, String>> =
+    Lazy::new(|| SETTINGS.read().http_client().map_err(|e| format!("{:#}", e)));
+
+static LINKS_REGEX: Lazy<Regex> = Lazy::new(|| regex::Regex::new("href=\"([^\"]*)\"").unwrap());
+static PAGE_TITLE: Lazy<Selector> = Lazy::new(|| Selector::parse("title").unwrap());
+
+// Enough to tell a web page from an archive, and to quote a short error message
+const SNIFF_LEN: usize = 1024;
+
+// Anything that knows where the actual file of an addon is
+pub(crate) trait LinkResolver {
+    async fn download_url(&self) -> Result<String>;
+    // Where a human would go to look at the addon
+    fn page_url(&self) -> String;
+}
+
+// A broken proxy or certificate is an error, falling back to a direct connection would bypass it
+pub fn client() -> Result<&'static reqwest::Client> {
+    CLIENT
+        .as_ref()
+        .map_err(|e| anyhow!("Bad network settings: {}", e))
+}
+
+pub async fn get_text(url: impl IntoUrl) -> Result<String> {
+    Ok(client()?.get(url).send().await?.text().await?)
+}
+
+pub fn links(html: &str) -> impl Iterator<Item = &str> {
+    LINKS_REGEX
+        .captures_iter(html)
+        .map(|c| c.get(1).unwrap().as_str())
+}
+
+pub fn find_link(html: &str, predicate: impl Fn(&str) -> bool) -> Option<&str> {
+    links(html).find(|l| predicate(l))
+}
+
+#[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Clone, Eq)]
+pub struct UrlLink {
+    url: String,
+}
+
+impl UrlLink {
+    pub fn new(url: String) -> Self {
+        Self { url }
+    }
+}
+
+impl LinkResolver for UrlLink {
+    async fn download_url(&self) -> Result<String> {
+        Ok(self.url.clone())
+    }
+
+    fn page_url(&self) -> String {
+        self.url.clone()
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct DownloadProgress {
+    pub file_name: Option<String>,
+    pub size: Option<u64>,
+    pub downloaded: u64,
+}
+
+pub fn print_progress(p: &DownloadProgress) {
+    println!(
+        "Downloading {}: {}/{}",
+        p.file_name.as_ref().map(|s| s.to_owned()).unwrap_or_default(), p.downloaded, p.size.unwrap_or(0)
+    );
+}
+
+// Content-Disposition and URLs come from the server, keep only a plain file name out of them
+pub fn sanitize_file_name(name: &str) -> Option<String> {
+    let name = name.split(';').next().unwrap_or_default();
+    let name = name.trim().trim_matches(|c| c == '"' || c == '\'').trim();
+    let name = std::path::Path::new(&name.replace('\\', "/"))
+        .file_name()?
+        .to_string_lossy()
+        .into_owned();
+    Some(name).filter(|n| !n.trim().is_empty())
+}
+
+pub struct Archive {
+    pub file: NamedTempFile,
+    pub file_name: String,
+}
+
+pub async fn download_archive(url: impl IntoUrl) -> Result<Archive> {
+    let url = url.into_url()?;
+    let url_name = url
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .and_then(sanitize_file_name)
+        .unwrap_or_else(|| "download".to_owned());
+
+    let mut file_name = None;
+    let buf = BufWriter::new(tempfile::NamedTempFile::new()?);
+    let file = download_file(url, buf, |p| {
+        file_name = p.file_name.clone();
+        print_progress(p);
+    })
+    .await?;
+
+    Ok(Archive {
+        file: file.into_inner().map_err(|e| e.into_error())?,
+        file_name: file_name
+            .as_deref()
+            .and_then(sanitize_file_name)
+            .unwrap_or(url_name),
+    })
+}
+
+pub async fn download_file<W: std::io::Write>(
+    url: impl IntoUrl,
+    mut file: W,
+    mut progress_callback: impl FnMut(&DownloadProgress),
+) -> Result<W> {
+    let regex = Regex::new(r#"filename ?= ?(?:"([^"]*)"|([^;\s]*))"#).unwrap();
+    let response = client()?.get(url).send().await?;
+    let url = response.url().clone();
+    let response = response
+        .error_for_status()
+        .with_context(|| format!("Download failed: {}", url))?;
+    let is_html = response
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|t| t.starts_with("text/html"));
+    if is_html {
+        // captcha, "mirror is busy" and friends come back as 200 OK pages
+        let page = response.text().await.unwrap_or_default();
+        bail!(not_a_file(&url, page.as_bytes()));
+    }
+    let filename = response
+        .headers()
+        .get(http::header::CONTENT_DISPOSITION)
+        .iter()
+        .flat_map(|h| h.to_str())
+        .flat_map(|s| regex.captures(s))
+        .flat_map(|c| c.get(1).or_else(|| c.get(2)))
+        .map(|m| m.as_str().to_owned())
+        .next();
+    let mut progress = DownloadProgress {
+        file_name: filename,
+        size: response.content_length(),
+        downloaded: 0,
+    };
+    progress_callback(&progress);
+
+    let (download_limit, connection_limit) = {
+        let settings = SETTINGS.read();
+        (settings.download_limit, settings.connection_limit)
+    };
+    GLOBAL_THROTTLE.set_rate(download_limit.map(|k| k as u64 * 1024));
+    let connection_throttle = Throttle::from_kib(connection_limit);
+
+    let mut stream = response.bytes_stream();
+    let mut last_progress = 0;
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    while let Some(item) = stream.next().await {
+        let chunk = item?;
+        if head.len() < SNIFF_LEN {
+            head.extend(chunk.iter().take(SNIFF_LEN - head.len()));
+            if head.len() == SNIFF_LEN && looks_like_html(&head) {
+                bail!(not_a_file(&url, &head));
+            }
+        }
+        file.write_all(&chunk)?;
+        throttle(&[&GLOBAL_THROTTLE, &connection_throttle], chunk.len() as u64).await;
+
+        progress.downloaded += chunk.len() as u64;
+        if progress.downloaded - last_progress > 1024 * 100 {     // a bit less pressure
+            progress_callback(&progress);
+            last_progress = progress.downloaded;
+        }
+    }
+
+    check_complete(&url, progress.size, progress.downloaded, &head)?;
+    Ok(file)
+}
+
+// Runs on the whole body: it has the promised length and isn't a short error page
+fn check_complete(
+    url: &reqwest::Url,
+    size: Option<u64>,
+    downloaded: u64,
+    head: &[u8],
+) -> Result<()> {
+    if let Some(size) = size.filter(|s| *s != downloaded) {
+        bail!(
+            "Download of {} was cut short: got {} of {} bytes",
+            url,
+            downloaded,
+            size
+        );
+    }
+    if head.len() < SNIFF_LEN && (looks_like_html(head) || is_plain_text(head)) {
+        bail!(not_a_file(url, head));
+    }
+    Ok(())
+}
+
+fn looks_like_html(head: &[u8]) -> bool {
+    let start = String::from_utf8_lossy(head).trim_start().to_lowercase();
+    start.starts_with("<!doctype html") || start.starts_with("<html") || start.starts_with("<?xml")
+}
+
+// Archives are binary, a few hundred bytes of text is an error message
+fn is_plain_text(head: &[u8]) -> bool {
+    !head.is_empty()
+        && std::str::from_utf8(head)
+            .is_ok_and(|s| s.chars().all(|c| !c.is_control() || c.is_whitespace()))
+}
+
+fn not_a_file(url: &reqwest::Url, body: &[u8]) -> String {
+    let body = String::from_utf8_lossy(body);
+    let title = Html::parse_document(&body)
+        .select(&PAGE_TITLE)
+        .next()
+        .map(|t| t.text().collect::<String>().trim().to_owned());
+    let hint = match title {
+        Some(title) if !title.is_empty() => title,
+        _ => body.trim().chars().take(200).collect(),
+    };
+    format!(
+        "{} returned a web page instead of a file, the site may be down or asking for a captcha: {}",
+        url, hint
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_complete, find_link, is_plain_text, links, looks_like_html, not_a_file,
+        sanitize_file_name,
+    };
+
+    #[test]
+    fn scrape_links() {
+        let html = r#"<a href="/addons/start/222467">Download</a>
+            <p>no link here</p>
+            <a class="button" href="https://www.moddb.com/downloads/mirror/222467/115/abc">mirror</a>"#;
+
+        assert_eq!(links(html).count(), 2);
+        assert_eq!(
+            find_link(html, |l| l.contains("downloads/mirror")),
+            Some("https://www.moddb.com/downloads/mirror/222467/115/abc")
+        );
+        assert_eq!(find_link(html, |l| l.ends_with(".zip")), None);
+    }
+
+    #[test]
+    fn error_pages() {
+        let captcha = b"\n  <!DOCTYPE html><html><head><title>Just a moment...</title></head></html>";
+        assert!(looks_like_html(captcha));
+        assert!(!looks_like_html(b"PK\x03\x04\x14\x00\x00\x00"));
+        assert!(!looks_like_html(b"7z\xbc\xaf\x27\x1c"));
+
+        assert!(is_plain_text(b"File not found\n"));
+        assert!(!is_plain_text(b"PK\x03\x04\x14\x00\x00\x00"));
+        assert!(!is_plain_text(b""));
+
+        let url = reqwest::Url::parse("https://www.moddb.com/downloads/mirror/1").unwrap();
+        assert!(not_a_file(&url, captcha).ends_with(": Just a moment..."));
+        assert!(not_a_file(&url, b"Mirror is busy").ends_with(": Mirror is busy"));
+    }
+
+    #[test]
+    fn incomplete_downloads() {
+        let url = reqwest::Url::parse("https://github.com/a/b/releases/download/1/c.7z").unwrap();
+        let archive = b"7z\xbc\xaf\x27\x1c\x00\x04";
+        assert!(check_complete(&url, Some(8), 8, archive).is_ok());
+        // servers that don't send a length can't be checked
+        assert!(check_complete(&url, None, 8, archive).is_ok());
+
+        let err = check_complete(&url, Some(1000), 8, archive).unwrap_err();
+        assert!(err.to_string().contains("cut short: got 8 of 1000 bytes"));
+        assert!(check_complete(&url, Some(8), 1000, archive).is_err());
+
+        assert!(check_complete(&url, Some(14), 14, b"File not found").is_err());
+    }
+
+    #[test]
+    fn file_names() {
+        let name = |s: &str| sanitize_file_name(s);
+        assert_eq!(name("Igigui.zip").as_deref(), Some("Igigui.zip"));
+        assert_eq!(name("\"Igigui 1.2.7z\"; size=100").as_deref(), Some("Igigui 1.2.7z"));
+        assert_eq!(name("Igigui.zip\";").as_deref(), Some("Igigui.zip"));
+        assert_eq!(name("../../evil.zip").as_deref(), Some("evil.zip"));
+        assert_eq!(name("..\\..\\evil.zip").as_deref(), Some("evil.zip"));
+        assert_eq!(name("/etc/passwd").as_deref(), Some("passwd"));
+        assert_eq!(name("C:\\Windows\\evil.dll").as_deref(), Some("evil.dll"));
+        assert_eq!(name(".."), None);
+        assert_eq!(name("\"\""), None);
+        assert_eq!(name(""), None);
+    }
+}
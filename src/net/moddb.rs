@@ -0,0 +1,146 @@
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use super::{get_text, LinkResolver};
+
+const URL_MODDB: &str = "https://www.moddb.com/mods/stalker-anomaly/addons/";
+const URL_MODDB_ROOT: &str = "https://www.moddb.com";
+
+static DOWNLOAD_BUTTON: Lazy<Selector> =
+    Lazy::new(|| Selector::parse("a#downloadmirrorstoggle, a.buttondownload").unwrap());
+// the "click here" link of the "your download will start shortly" widget
+static MIRROR_LINK: Lazy<Selector> =
+    Lazy::new(|| Selector::parse(r#"#downloadon a[href*="/downloads/mirror/"]"#).unwrap());
+static SIDEBAR_ROW: Lazy<Selector> = Lazy::new(|| Selector::parse("div.row").unwrap());
+static ROW_TITLE: Lazy<Selector> = Lazy::new(|| Selector::parse("h5").unwrap());
+static ROW_TIME: Lazy<Selector> = Lazy::new(|| Selector::parse("time").unwrap());
+
+#[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Clone, Eq)]
+pub struct ModdbLink {
+    pub addon_link: String,
+    pub updated: String,
+}
+
+fn absolute(link: &str) -> String {
+    if link.starts_with('/') {
+        format!("{}{}", URL_MODDB_ROOT, link)
+    } else {
+        link.to_owned()
+    }
+}
+
+fn download_button(page: &str) -> Result<String> {
+    Html::parse_document(page)
+        .select(&DOWNLOAD_BUTTON)
+        .filter_map(|a| a.value().attr("href"))
+        .find(|href| href.contains("/start/"))
+        .map(absolute)
+        .ok_or_else(|| anyhow!("Couldn't find moddb download button"))
+}
+
+fn mirror_link(page: &str) -> Result<String> {
+    Html::parse_document(page)
+        .select(&MIRROR_LINK)
+        .filter_map(|a| a.value().attr("href"))
+        .next()
+        .map(absolute)
+        .ok_or_else(|| anyhow!("Couldn't find moddb mirror link"))
+}
+
+// The "Updated" row of the addon sidebar, as shown on the page ("Aug 8th, 2022")
+fn updated_date(page: &str) -> Option<String> {
+    let doc = Html::parse_document(page);
+    let row = doc.select(&SIDEBAR_ROW).find(|row| {
+        row.select(&ROW_TITLE)
+            .next()
+            .is_some_and(|h| h.text().collect::<String>().trim() == "Updated")
+    })?;
+    let time = row.select(&ROW_TIME).next()?;
+    Some(time.text().collect::<String>().trim().to_owned())
+}
+
+impl ModdbLink {
+    pub async fn fetch_updated(&self) -> Result<String> {
+        let page = get_text(self.page_url()).await?;
+        updated_date(&page).ok_or_else(|| anyhow!("Couldn't find moddb update date"))
+    }
+}
+
+impl LinkResolver for ModdbLink {
+    async fn download_url(&self) -> Result<String> {
+        let page = get_text(self.page_url()).await?;
+        let start = download_button(&page)?;
+
+        let page = get_text(start).await?;
+        mirror_link(&page)
+    }
+
+    fn page_url(&self) -> String {
+        format!("{url}{addon}", url = URL_MODDB, addon = self.addon_link)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::{client, LinkResolver};
+
+    use super::{download_button, mirror_link, updated_date, ModdbLink};
+
+    static ADDON_PAGE: &str = r#"<html><body>
+        <div class="table tablemenu">
+            <div class="row clear">
+                <h5>Filename</h5>
+                <span class="summary">Anomaly-Mod-Configuration-Menu.zip</span>
+            </div>
+            <div class="row clear">
+                <h5>Added</h5>
+                <span class="summary"><time datetime="2020-06-06T12:00:00+00:00">Jun 6th, 2020</time></span>
+            </div>
+            <div class="row clear">
+                <h5>Updated</h5>
+                <span class="summary"><time datetime="2022-08-08T12:00:00+00:00">Aug 8th, 2022</time></span>
+            </div>
+        </div>
+        <a href="/addons/start/222467/all">Report</a>
+        <a href="/addons/start/222467" class="buttonwhite buttondownload" id="downloadmirrorstoggle">Download now</a>
+    </body></html>"#;
+
+    static START_PAGE: &str = r#"<html><body>
+        <a href="https://www.moddb.com/mods/stalker-anomaly/addons/anomaly-mod-configuration-menu">Back</a>
+        <a href="/downloads/mirror/1/1/another-addon/">Popular download</a>
+        <p id="downloadon">Your download will start shortly, if it does not
+            <a href="/downloads/mirror/222467/124/4a35c6a1e5a2f3fd5e2f8c2c5d1c7d21/">click here</a>
+        </p>
+    </body></html>"#;
+
+    #[test]
+    fn moddb_pages() {
+        assert_eq!(
+            download_button(ADDON_PAGE).unwrap(),
+            "https://www.moddb.com/addons/start/222467"
+        );
+        assert_eq!(
+            mirror_link(START_PAGE).unwrap(),
+            "https://www.moddb.com/downloads/mirror/222467/124/4a35c6a1e5a2f3fd5e2f8c2c5d1c7d21/"
+        );
+        assert_eq!(updated_date(ADDON_PAGE).as_deref(), Some("Aug 8th, 2022"));
+
+        assert!(download_button(START_PAGE).is_err());
+        assert!(mirror_link(ADDON_PAGE).is_err());
+        assert_eq!(updated_date(START_PAGE), None);
+    }
+
+    #[tokio::test]
+    async fn moddb_link() {
+        let key = ModdbLink {
+            addon_link: "anomaly-mod-configuration-menu".to_owned(),
+            updated: "Aug 8th, 2022".to_owned(),
+        };
+        let url = key.download_url().await.unwrap();
+
+        let response = client().unwrap().head(url).send().await.unwrap();
+        assert!(response.content_length().is_some()); // just check if it has size
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+// Shared by every download running at the same time
+pub static GLOBAL_THROTTLE: Lazy<Throttle> = Lazy::new(|| Throttle::new(None));
+
+struct Bucket {
+    rate: Option<u64>,
+    available: f64,
+    last: Instant,
+}
+
+// Token bucket. Bytes are taken on credit and the caller sleeps the debt off,
+// so any number of downloads can share one throttle without coordinating.
+pub struct Throttle(Mutex<Bucket>);
+
+impl Throttle {
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self(Mutex::new(Bucket {
+            rate: bytes_per_sec,
+            available: bytes_per_sec.unwrap_or(0) as f64,
+            last: Instant::now(),
+        }))
+    }
+
+    pub fn from_kib(kib_per_sec: Option<u32>) -> Self {
+        Self::new(kib_per_sec.map(|k| k as u64 * 1024))
+    }
+
+    pub fn set_rate(&self, bytes_per_sec: Option<u64>) {
+        let mut bucket = self.0.lock();
+        if bucket.rate != bytes_per_sec {
+            bucket.rate = bytes_per_sec;
+            bucket.available = bucket.available.min(bytes_per_sec.unwrap_or(0) as f64);
+        }
+    }
+
+    pub fn reserve(&self, bytes: u64) -> Duration {
+        let mut bucket = self.0.lock();
+        let Some(rate) = bucket.rate.filter(|r| *r > 0) else {
+            return Duration::ZERO;
+        };
+
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.last).as_secs_f64() * rate as f64;
+        // burst of at most one second worth of bytes
+        bucket.available = (bucket.available + refill).min(rate as f64);
+        bucket.last = now;
+        bucket.available -= bytes as f64;
+
+        if bucket.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.available / rate as f64)
+        }
+    }
+}
+
+pub async fn throttle(throttles: &[&Throttle], bytes: u64) {
+    let wait = throttles
+        .iter()
+        .map(|t| t.reserve(bytes))
+        .max()
+        .unwrap_or_default();
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Throttle;
+
+    #[test]
+    fn unlimited() {
+        let throttle = Throttle::new(None);
+        assert_eq!(throttle.reserve(1024 * 1024 * 1024), Duration::ZERO);
+    }
+
+    #[test]
+    fn debt() {
+        let throttle = Throttle::new(Some(1000));
+        assert_eq!(throttle.reserve(1000), Duration::ZERO);
+        let wait = throttle.reserve(2000);
+        assert!(wait > Duration::from_millis(1900) && wait <= Duration::from_secs(2));
+        // debt adds up for whoever comes next
+        let wait = throttle.reserve(1000);
+        assert!(wait > Duration::from_millis(2900) && wait <= Duration::from_secs(3));
+    }
+
+    #[test]
+    fn change_rate() {
+        let throttle = Throttle::new(Some(1000));
+        throttle.set_rate(None);
+        assert_eq!(throttle.reserve(1_000_000), Duration::ZERO);
+        throttle.set_rate(Some(100));
+        assert!(throttle.reserve(200) > Duration::from_millis(1900));
+    }
+}
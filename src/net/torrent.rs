@@ -0,0 +1,137 @@
+use std::{
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use librqbit::{
+    limits::LimitsConfig, AddTorrent, AddTorrentOptions, AddTorrentResponse, ConnectionOptions,
+    DhtSessionConfig, Session, SessionOptions,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use super::DownloadProgress;
+use crate::settings::SETTINGS;
+
+// One session per process, it downloads into the folder of the first torrent
+// and keeps seeding finished torrents for as long as the tool runs
+static SESSION: OnceCell<(PathBuf, Arc<Session>)> = OnceCell::const_new();
+
+async fn session(default_dir: &Path) -> Result<&'static Arc<Session>> {
+    let (dir, session) = SESSION
+        .get_or_try_init(|| async {
+            let settings = SETTINGS.read().clone();
+            // peers and trackers only speak socks here
+            let proxy_url = match &settings.proxy {
+                Some(proxy) if proxy.is_socks() => Some(proxy.url_with_auth()?.to_string()),
+                _ => None,
+            };
+            let opts = SessionOptions {
+                dht: Some(DhtSessionConfig {
+                    persistence: None,
+                    ..Default::default()
+                }),
+                ratelimits: LimitsConfig {
+                    download_bps: settings
+                        .download_limit
+                        .and_then(|k| NonZeroU32::new(k.saturating_mul(1024))),
+                    upload_bps: None,
+                },
+                connect: Some(ConnectionOptions {
+                    proxy_url,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let session = Session::new_with_opts(default_dir.to_owned(), opts).await?;
+            anyhow::Ok((default_dir.to_owned(), session))
+        })
+        .await?;
+    if dir != default_dir {
+        bail!("Torrents already download into {}", dir.display());
+    }
+    Ok(session)
+}
+
+// Torrents still in the session, they are the ones being seeded
+pub fn seeding_torrents() -> usize {
+    SESSION
+        .get()
+        .map_or(0, |(_, session)| session.with_torrents(|t| t.count()))
+}
+
+#[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Clone, Eq)]
+pub struct TorrentLink {
+    pub magnet: String,
+}
+
+impl TorrentLink {
+    pub fn new(magnet: String) -> Self {
+        Self { magnet }
+    }
+
+    // Returns the downloaded file, or `out_dir/<torrent name>` if it has more than one file.
+    // Without `seed` the torrent is dropped from the session when done.
+    pub async fn download(
+        &self,
+        out_dir: &Path,
+        seed: bool,
+        mut progress_callback: impl FnMut(&DownloadProgress),
+    ) -> Result<PathBuf> {
+        std::fs::create_dir_all(out_dir)?;
+        let session = session(out_dir).await?;
+        // without output_folder librqbit gives multi-file torrents a folder of their own
+        let opts = AddTorrentOptions {
+            // lets a killed install pick up the pieces that are already on disk
+            overwrite: true,
+            ..Default::default()
+        };
+
+        let handle = match session
+            .add_torrent(AddTorrent::from_url(self.magnet.as_str()), Some(opts))
+            .await?
+        {
+            AddTorrentResponse::Added(_, handle) => handle,
+            AddTorrentResponse::AlreadyManaged(_, handle) => handle,
+            AddTorrentResponse::ListOnly(_) => bail!("Torrent was only listed"),
+        };
+        handle.wait_until_initialized().await?;
+
+        let mut progress = DownloadProgress::default();
+        loop {
+            let stats = handle.stats();
+            if let Some(e) = stats.error {
+                bail!("Torrent failed: {}", e);
+            }
+            progress.file_name = handle.name();
+            progress.size = Some(stats.total_bytes);
+            progress.downloaded = stats.progress_bytes;
+            progress_callback(&progress);
+
+            if stats.finished {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+
+        let files = handle.with_metadata(|m| {
+            m.file_infos
+                .iter()
+                .map(|f| f.relative_filename.clone())
+                .collect::<Vec<_>>()
+        })?;
+        let content = match files.as_slice() {
+            [file] => handle.output_folder().join(file),
+            [] => return Err(anyhow!("Torrent has no files")),
+            _ => handle.output_folder().to_owned(),
+        };
+
+        if !seed {
+            session.delete(handle.id().into(), false).await?;
+        }
+        Ok(content)
+    }
+}
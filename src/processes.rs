@@ -0,0 +1,95 @@
+use anyhow::{bail, Result};
+
+use crate::actions::hidden_command;
+
+// MO2 holds its mods and rewrites modlist.txt on exit, the game locks its exes and gamedata
+fn is_blocker(name: &str, own_exe: &str) -> bool {
+    let lower = name.to_lowercase();
+    if lower == own_exe {
+        return false;
+    }
+    lower == "modorganizer.exe" || (lower.starts_with("anomaly") && lower.ends_with(".exe"))
+}
+
+// `tasklist /fo csv /nh` prints "Image Name","PID","Session Name","Session#","Mem Usage"
+fn parse_tasklist(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|l| l.strip_prefix('"'))
+        .filter_map(|l| l.split_once('"'))
+        .map(|(name, _)| name.to_owned())
+        .collect()
+}
+
+pub fn running_blockers() -> Result<Vec<String>> {
+    let output = hidden_command("tasklist")
+        .args(["/fo", "csv", "/nh"])
+        .output()?;
+    if !output.status.success() {
+        bail!("tasklist failed");
+    }
+
+    let own_exe = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_lowercase()))
+        .unwrap_or_default();
+    let mut running: Vec<String> = parse_tasklist(&String::from_utf8_lossy(&output.stdout))
+        .into_iter()
+        .filter(|n| is_blocker(n, &own_exe))
+        .collect();
+    running.sort();
+    running.dedup();
+    Ok(running)
+}
+
+// Asks until everything is closed, `prompt` returns false when the user gives up
+pub fn wait_until_closed(mut prompt: impl FnMut(&[String]) -> bool) -> Result<()> {
+    loop {
+        let running = running_blockers().unwrap_or_else(|e| {
+            println!("Can't check running programs: {}", e);
+            Vec::new()
+        });
+        if running.is_empty() {
+            return Ok(());
+        }
+        if !prompt(&running) {
+            bail!("Close {} before installing", running.join(", "));
+        }
+    }
+}
+
+pub fn console_prompt(running: &[String]) -> bool {
+    println!(
+        "{} is running, close it and press Enter to continue, or type q to abort",
+        running.join(", ")
+    );
+    let mut answer = String::new();
+    // closed or broken stdin can't answer, that's an abort too
+    matches!(std::io::stdin().read_line(&mut answer), Ok(n) if n > 0) && answer.trim() != "q"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_blocker, parse_tasklist};
+
+    #[test]
+    fn blockers() {
+        let tasklist = "\"System Idle Process\",\"0\",\"Services\",\"0\",\"8 K\"\r\n\
+            \"ModOrganizer.exe\",\"4242\",\"Console\",\"1\",\"120,512 K\"\r\n\
+            \"AnomalyDX11AVX.exe\",\"5151\",\"Console\",\"1\",\"4,120,512 K\"\r\n\
+            \"anomaly_modder_tool.exe\",\"6000\",\"Console\",\"1\",\"20,000 K\"\r\n";
+
+        let names = parse_tasklist(tasklist);
+        assert_eq!(names.len(), 4);
+        let blockers: Vec<&String> = names
+            .iter()
+            .filter(|n| is_blocker(n, "anomaly_modder_tool.exe"))
+            .collect();
+        assert_eq!(blockers, ["ModOrganizer.exe", "AnomalyDX11AVX.exe"]);
+
+        assert!(
+            parse_tasklist("INFO: No tasks are running which match the specified criteria.")
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::InstanceConfigData, journal::STAGING_DIR, mo2::Mo2Instance};
+
+pub const MODDED_EXES_BACKUP: &str = "BACKUP_Vanilla_exes";
+pub const MO2_BACKUP: &str = "BACKUP";
+pub static BACKUP_DIRS: &[&str] = &[MODDED_EXES_BACKUP, MO2_BACKUP];
+
+// Sizes in bytes, archive_size is unknown for torrents resumed from staging
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AddonStats {
+    pub unpacked_size: u64,
+    pub archive_size: Option<u64>,
+}
+
+pub fn dir_size(dir: &Path) -> u64 {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+#[derive(Debug, Default)]
+pub struct DiskUsage {
+    pub addons: Vec<(String, AddonStats)>,
+    pub downloads: u64,
+    pub backups: u64,
+    pub staging: u64,
+}
+
+impl DiskUsage {
+    pub fn collect(anomaly_dir: &Path, mo2: &Mo2Instance) -> Result<Self> {
+        let instance = InstanceConfigData::load(&mo2.base_dir)?;
+        let mut addons: Vec<(String, AddonStats)> = instance
+            .stats()
+            .iter()
+            .filter(|(addon, _)| mo2.mods_dir.join(addon).is_dir())
+            .map(|(addon, stats)| (addon.clone(), *stats))
+            .collect();
+        addons.sort_by_key(|(_, a)| Reverse(a.unpacked_size));
+
+        Ok(Self {
+            addons,
+            downloads: placed_downloads(&instance, mo2).map(|p| dir_size(&p)).sum(),
+            backups: backup_dirs(anomaly_dir).iter().map(|d| dir_size(d)).sum(),
+            staging: dir_size(&mo2.base_dir.join(STAGING_DIR)),
+        })
+    }
+
+    pub fn addons_total(&self) -> u64 {
+        self.addons.iter().map(|(_, s)| s.unpacked_size).sum()
+    }
+}
+
+fn backup_dirs(anomaly_dir: &Path) -> Vec<PathBuf> {
+    BACKUP_DIRS
+        .iter()
+        .map(|d| anomaly_dir.join(d))
+        .filter(|d| d.is_dir())
+        .collect()
+}
+
+// Only what we put there ourselves, the rest of MO2 downloads belongs to the user
+fn placed_downloads<'a>(
+    instance: &'a InstanceConfigData,
+    mo2: &'a Mo2Instance,
+) -> impl Iterator<Item = PathBuf> + 'a {
+    instance.downloads().iter().flat_map(|name| {
+        [
+            mo2.downloads_dir.join(name),
+            mo2.downloads_dir.join(format!("{}.meta", name)),
+        ]
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cleanup {
+    Downloads,
+    Backups,
+    Staging,
+}
+
+impl Cleanup {
+    pub fn run(self, anomaly_dir: &Path, mo2: &Mo2Instance) -> Result<()> {
+        let dirs = match self {
+            Cleanup::Downloads => {
+                let mut instance = InstanceConfigData::load(&mo2.base_dir)?;
+                for path in placed_downloads(&instance, mo2) {
+                    if path.is_dir() {
+                        std::fs::remove_dir_all(&path)?;
+                    } else if path.is_file() {
+                        std::fs::remove_file(&path)?;
+                    }
+                }
+                instance.forget_downloads();
+                return instance.save();
+            }
+            Cleanup::Backups => backup_dirs(anomaly_dir),
+            Cleanup::Staging => vec![mo2.base_dir.join(STAGING_DIR)],
+        };
+        for dir in dirs.iter().filter(|d| d.is_dir()) {
+            std::fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn human_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, units[unit]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::{config::InstanceConfigData, mo2::Mo2Instance};
+
+    use super::{dir_size, human_size, Cleanup, DiskUsage};
+
+    #[test]
+    fn sizes() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        std::fs::write(dir.path().join("a/b/c.ltx"), [0; 1000]).unwrap();
+        std::fs::write(dir.path().join("d.script"), [0; 24]).unwrap();
+        assert_eq!(dir_size(dir.path()), 1024);
+        assert_eq!(dir_size(&dir.path().join("missing")), 0);
+
+        assert_eq!(human_size(1000), "1000 B");
+        assert_eq!(human_size(1024), "1.0 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024 * 1024 / 2), "1.5 GiB");
+    }
+
+    #[test]
+    fn cleanup() {
+        let anomaly = tempdir().unwrap();
+        let mo_dir = anomaly.path().join("mo2");
+        let mo2 = Mo2Instance::open(&mo_dir).unwrap();
+        std::fs::create_dir_all(mo2.downloads_dir.join("torrent")).unwrap();
+        std::fs::write(mo2.downloads_dir.join("torrent/b.7z"), "7z").unwrap();
+        std::fs::write(mo2.downloads_dir.join("a.zip"), "zip").unwrap();
+        std::fs::write(mo2.downloads_dir.join("a.zip.meta"), "meta").unwrap();
+        std::fs::write(mo2.downloads_dir.join("users.zip"), "user").unwrap();
+        std::fs::create_dir_all(anomaly.path().join("BACKUP_Vanilla_exes/bin")).unwrap();
+        std::fs::create_dir_all(anomaly.path().join("BACKUP")).unwrap();
+        std::fs::write(anomaly.path().join("BACKUP/ModOrganizer.ini"), "ini").unwrap();
+
+        let mut instance = InstanceConfigData::load(&mo2.base_dir).unwrap();
+        instance.record_download("a.zip");
+        instance.record_download("torrent");
+        instance.record_download("already_gone.zip");
+        instance.save().unwrap();
+        let usage = DiskUsage::collect(anomaly.path(), &mo2).unwrap();
+        assert_eq!(usage.downloads, 9);
+        assert_eq!(usage.backups, 3);
+
+        Cleanup::Downloads.run(anomaly.path(), &mo2).unwrap();
+        assert_eq!(dir_size(&mo2.downloads_dir), 4);
+        assert!(mo2.downloads_dir.join("users.zip").is_file());
+        let instance = InstanceConfigData::load(&mo2.base_dir).unwrap();
+        assert!(instance.downloads().is_empty());
+
+        Cleanup::Backups.run(anomaly.path(), &mo2).unwrap();
+        assert!(!anomaly.path().join("BACKUP_Vanilla_exes").exists());
+        assert!(!anomaly.path().join("BACKUP").exists());
+        // nothing to clean is fine
+        Cleanup::Staging.run(anomaly.path(), &mo2).unwrap();
+    }
+}
@@ -0,0 +1,228 @@
+use std::{fmt::Display, path::Path};
+
+use anyhow::Result;
+
+use crate::{addonlist::Modpack, journal::InstallJournal, mo2::Mo2Instance};
+
+// Only reads the instance, so it's safe to run next to a running MO2 or a stuck install
+#[derive(Debug, Default)]
+pub struct HealthReport {
+    pub mo2: Option<Mo2Instance>,
+    pub missing: Vec<String>,
+    pub unknown: Vec<String>,
+    pub broken_links: Vec<String>,
+    pub empty: Vec<String>,
+    pub modlist: Option<ModlistStatus>,
+    pub journal: Option<JournalStatus>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ModlistStatus {
+    pub not_listed: Vec<String>,
+    pub disabled: Vec<String>,
+    pub no_folder: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct JournalStatus {
+    pub interrupted: Vec<String>,
+    pub staged: usize,
+}
+
+impl HealthReport {
+    pub fn collect(anomaly_dir: &Path, pack: &Modpack) -> Result<Self> {
+        let Some(mo2) = Mo2Instance::detect(anomaly_dir)? else {
+            return Ok(Self::default());
+        };
+
+        let mut report = Self {
+            missing: pack
+                .missing_addons(&mo2.mods_dir)
+                .into_iter()
+                .map(str::to_owned)
+                .collect(),
+            ..Default::default()
+        };
+        if mo2.mods_dir.is_dir() {
+            report.unknown = pack.unknown_addons(&mo2.mods_dir)?;
+            (report.broken_links, report.empty) = check_mod_folders(&mo2.mods_dir)?;
+        }
+
+        let modlist = mo2.profiles_dir.join("Default").join("modlist.txt");
+        report.modlist = match std::fs::read_to_string(&modlist) {
+            Ok(s) => Some(check_modlist(&s, pack.load_order(), &mo2.mods_dir)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        if InstallJournal::exists(&mo2.base_dir) {
+            let journal = InstallJournal::load(&mo2.base_dir)?;
+            report.journal = Some(JournalStatus {
+                interrupted: journal.interrupted().into_iter().map(str::to_owned).collect(),
+                staged: journal.staged_count(),
+            });
+        }
+
+        report.mo2 = Some(mo2);
+        Ok(report)
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.mo2.is_some()
+            && self.missing.is_empty()
+            && self.broken_links.is_empty()
+            && self.empty.is_empty()
+            && self.modlist.as_ref() == Some(&ModlistStatus::default())
+            && self.journal.is_none()
+    }
+}
+
+fn check_mod_folders(mods_dir: &Path) -> Result<(Vec<String>, Vec<String>)> {
+    let mut broken = Vec::new();
+    let mut empty = Vec::new();
+    for dir in std::fs::read_dir(mods_dir)? {
+        let dir = dir?;
+        let name = dir.file_name().to_string_lossy().into_owned();
+        let path = dir.path();
+        if dir.file_type()?.is_symlink() && !path.exists() {
+            broken.push(name);
+        } else if path.is_dir() && is_empty_mod(&path)? {
+            empty.push(name);
+        }
+    }
+    Ok((broken, empty))
+}
+
+// MO2 writes a meta.ini into every mod folder, that alone doesn't count as content
+fn is_empty_mod(dir: &Path) -> Result<bool> {
+    for entry in std::fs::read_dir(dir)? {
+        if entry?.file_name() != "meta.ini" {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn check_modlist(modlist: &str, order: &[String], mods_dir: &Path) -> ModlistStatus {
+    let mut status = ModlistStatus::default();
+    let mut listed = Vec::new();
+    for line in modlist.lines().map(str::trim) {
+        let (enabled, name) = if let Some(name) = line.strip_prefix('+') {
+            (true, name)
+        } else if let Some(name) = line.strip_prefix('-') {
+            (false, name)
+        } else {
+            // comments, unmanaged DLC entries (*) and junk
+            continue;
+        };
+        if name.ends_with("_separator") {
+            continue;
+        }
+        if !mods_dir.join(name).is_dir() {
+            status.no_folder.push(name.to_owned());
+        }
+        if !enabled && order.iter().any(|a| a == name) {
+            status.disabled.push(name.to_owned());
+        }
+        listed.push(name);
+    }
+    status.not_listed = order
+        .iter()
+        .filter(|a| !listed.contains(&a.as_str()))
+        .cloned()
+        .collect();
+    status
+}
+
+impl Display for HealthReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |f: &mut std::fmt::Formatter<'_>, title: &str, items: &[String]| {
+            if items.is_empty() {
+                return Ok(());
+            }
+            writeln!(f, "{} ({}):", title, items.len())?;
+            items.iter().try_for_each(|i| writeln!(f, "  {}", i))
+        };
+
+        let Some(mo2) = &self.mo2 else {
+            return writeln!(f, "MO2: not found");
+        };
+        writeln!(
+            f,
+            "MO2: {} (version {})",
+            mo2.base_dir.display(),
+            mo2.version.as_deref().unwrap_or("unknown")
+        )?;
+
+        list(f, "Missing addons", &self.missing)?;
+        list(f, "Unknown addons", &self.unknown)?;
+        list(f, "Broken links", &self.broken_links)?;
+        list(f, "Empty mod folders", &self.empty)?;
+        match &self.modlist {
+            Some(modlist) => {
+                list(f, "Not in modlist.txt", &modlist.not_listed)?;
+                list(f, "Disabled in modlist.txt", &modlist.disabled)?;
+                list(f, "In modlist.txt without a folder", &modlist.no_folder)?;
+            }
+            None => writeln!(f, "No modlist.txt in the Default profile")?,
+        }
+        if let Some(journal) = &self.journal {
+            writeln!(
+                f,
+                "Unfinished install: {} staged downloads",
+                journal.staged
+            )?;
+            list(f, "Interrupted addons", &journal.interrupted)?;
+        }
+
+        if self.is_healthy() {
+            writeln!(f, "Everything looks fine")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::{check_mod_folders, check_modlist, ModlistStatus};
+
+    #[test]
+    fn mod_folders() {
+        let mods = tempdir().unwrap();
+        let mods = mods.path();
+        std::fs::create_dir_all(mods.join("Igigui/gamedata")).unwrap();
+        std::fs::create_dir(mods.join("Empty")).unwrap();
+        std::fs::write(mods.join("Empty/meta.ini"), "[General]\n").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(mods.join("gone"), mods.join("Linked")).unwrap();
+
+        let (broken, empty) = check_mod_folders(mods).unwrap();
+        assert_eq!(empty, ["Empty"]);
+        #[cfg(unix)]
+        assert_eq!(broken, ["Linked"]);
+        #[cfg(not(unix))]
+        assert!(broken.is_empty());
+    }
+
+    #[test]
+    fn modlist() {
+        let mods = tempdir().unwrap();
+        for addon in ["Igigui", "Interactive_PDA", "Arszi_Task_Pack"] {
+            std::fs::create_dir(mods.path().join(addon)).unwrap();
+        }
+        let order = ["Igigui", "Interactive_PDA", "Arszi_Task_Pack", "anomaly-speed"]
+            .map(str::to_owned);
+
+        let modlist = "# header\n+Igigui\n-Interactive_PDA\n+Arszi_Task_Pack\n+Removed\n*DLC: Anomaly\n+Misc_separator\n";
+        assert_eq!(
+            check_modlist(modlist, &order, mods.path()),
+            ModlistStatus {
+                not_listed: vec!["anomaly-speed".to_owned()],
+                disabled: vec!["Interactive_PDA".to_owned()],
+                no_folder: vec!["Removed".to_owned()],
+            }
+        );
+    }
+}
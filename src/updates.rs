@@ -0,0 +1,193 @@
+use std::{
+    fmt::Display,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{
+    actions::{InstallModdedExes, MODDED_EXES_RECORD},
+    addonlist::{AddonKey, FolderEntry},
+    app::AppContext,
+    config::{InstanceConfigData, BUNDLED_CONFIG},
+    mo2::Mo2Instance,
+    net::{get_text, GithubLink},
+    settings::SETTINGS,
+};
+
+static REPO_TOOL: &str = "Igigog/anomaly-modding-tool";
+static URL_REMOTE_CONFIG: &str =
+    "https://raw.githubusercontent.com/Igigog/anomaly-modding-tool/main/resources/config.json";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Update {
+    Addon { name: String, version: String },
+    Modpack,
+    ModdedExes,
+    Tool { version: String },
+}
+
+impl Display for Update {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Update::Addon { name, version } => write!(f, "{}: {}", name, version),
+            Update::Modpack => write!(f, "Modpack config has changed"),
+            Update::ModdedExes => write!(f, "New modded exes"),
+            Update::Tool { version } => write!(f, "Anomaly Modding Tool {}", version),
+        }
+    }
+}
+
+// "v0.2.10" > "0.2.9", anything unparsable is compared as a plain string
+fn is_newer(tag: &str, current: &str) -> bool {
+    let parse = |v: &str| {
+        v.trim_start_matches('v')
+            .split('.')
+            .map(str::parse::<u32>)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    match (parse(tag), parse(current)) {
+        (Ok(tag), Ok(current)) => tag > current,
+        _ => tag.trim_start_matches('v') != current.trim_start_matches('v'),
+    }
+}
+
+fn config_changed(bundled: &str, remote: &str) -> Result<bool> {
+    let bundled: serde_json::Value = serde_json::from_str(bundled)?;
+    let remote: serde_json::Value = serde_json::from_str(remote)?;
+    Ok(bundled != remote)
+}
+
+async fn addon_update(key: &AddonKey) -> Result<Option<String>> {
+    match key {
+        AddonKey::Moddb(link) => {
+            let updated = link.fetch_updated().await?;
+            Ok((updated != link.updated).then_some(updated))
+        }
+        // "latest" is always installed fresh, only pinned tags can be outdated
+        AddonKey::Github(link) if link.tag != "latest" => {
+            let tag = link.latest_tag().await?;
+            Ok((tag != link.tag).then_some(tag))
+        }
+        _ => Ok(None),
+    }
+}
+
+async fn check_addons(mo2: &Mo2Instance) -> Result<Vec<Update>> {
+    let instance = InstanceConfigData::load(&mo2.base_dir)?;
+    let mut installed: Vec<(&str, &FolderEntry)> = instance
+        .installed()
+        .filter(|(name, _)| mo2.mods_dir.join(name).is_dir())
+        .collect();
+    installed.sort_by_key(|(name, _)| *name);
+
+    let mut updates = Vec::new();
+    for (name, entry) in installed {
+        match addon_update(&entry.download).await {
+            Ok(Some(version)) => updates.push(Update::Addon {
+                name: name.to_owned(),
+                version,
+            }),
+            Ok(None) => {}
+            Err(e) => println!("Update check for {} failed: {}", name, e),
+        }
+    }
+    Ok(updates)
+}
+
+async fn check_modded_exes(anomaly_dir: &Path) -> Result<bool> {
+    let installed = match std::fs::read_to_string(anomaly_dir.join(MODDED_EXES_RECORD)) {
+        Ok(url) => url,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(InstallModdedExes::modded_exes_url().await? != installed.trim())
+}
+
+async fn check_tool() -> Result<Option<String>> {
+    let link = GithubLink {
+        repo: REPO_TOOL.to_owned(),
+        tag: "latest".to_owned(),
+        filename: String::new(),
+    };
+    let tag = link.latest_tag().await?;
+    Ok(is_newer(&tag, env!("CARGO_PKG_VERSION")).then_some(tag))
+}
+
+pub async fn check_updates(ctx: &AppContext) -> Vec<Update> {
+    let mut updates = Vec::new();
+    let report = |what: &str, e: anyhow::Error| println!("Update check for {} failed: {}", what, e);
+
+    if let Some(mo2) = &ctx.mo2 {
+        match check_addons(mo2).await {
+            Ok(addons) => updates.extend(addons),
+            Err(e) => report("addons", e),
+        }
+    }
+    match get_text(URL_REMOTE_CONFIG).await.and_then(|c| config_changed(BUNDLED_CONFIG, &c)) {
+        Ok(true) => updates.push(Update::Modpack),
+        Ok(false) => {}
+        Err(e) => report("modpack config", e),
+    }
+    match check_modded_exes(&ctx.anomaly_dir).await {
+        Ok(true) => updates.push(Update::ModdedExes),
+        Ok(false) => {}
+        Err(e) => report("modded exes", e),
+    }
+    match check_tool().await {
+        Ok(Some(version)) => updates.push(Update::Tool { version }),
+        Ok(None) => {}
+        Err(e) => report("the tool", e),
+    }
+    updates
+}
+
+// Runs until the app exits, the interval is re-read so settings changes apply without restart
+pub fn spawn_checker(ctx: Arc<AppContext>, repaint: impl Fn() + Send + 'static) {
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut last_check: Option<Instant> = None;
+        loop {
+            let interval = SETTINGS
+                .read()
+                .update_interval
+                .map(|h| Duration::from_secs(u64::from(h) * 3600));
+            if let Some(interval) = interval {
+                if last_check.is_none_or(|t| t.elapsed() >= interval) {
+                    let updates = runtime.block_on(check_updates(&ctx));
+                    *ctx.updates.lock() = updates;
+                    last_check = Some(Instant::now());
+                    repaint();
+                }
+            }
+            std::thread::sleep(Duration::from_secs(60));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{config_changed, is_newer};
+
+    #[test]
+    fn versions() {
+        assert!(is_newer("v0.2.10", "0.2.9"));
+        assert!(is_newer("0.3", "0.2.9"));
+        assert!(!is_newer("v0.1.0", "0.1.0"));
+        assert!(!is_newer("0.0.9", "0.1.0"));
+        assert!(is_newer("nightly-2", "0.1.0"));
+    }
+
+    #[test]
+    fn changed_config() {
+        let bundled = r#"{"metadata": {"config_version": 1, "name": "Pack"}, "mods": {}}"#;
+        let reformatted = "{\n  \"mods\": {},\n  \"metadata\": {\"name\": \"Pack\", \"config_version\": 1}\n}";
+        assert!(!config_changed(bundled, reformatted).unwrap());
+
+        let remote = r#"{"metadata": {"config_version": 1, "name": "Pack"}, "mods": {"a": {}}}"#;
+        assert!(config_changed(bundled, remote).unwrap());
+        assert!(config_changed(bundled, "<html>404</html>").is_err());
+    }
+}